@@ -1,7 +1,7 @@
 use copypasta::{ClipboardContext, ClipboardProvider};
 
 fn main() {
-    let mut ctx = ClipboardContext::new().unwrap();
+    let ctx = ClipboardContext::new().unwrap();
 
     let the_string = "Hello, world!";
 
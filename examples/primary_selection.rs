@@ -1,18 +1,22 @@
-// #[cfg(target_os = "linux")]
-// use copypasta::x11_clipboard::{Primary, X11ClipboardContext};
-// #[cfg(target_os = "linux")]
-// use copypasta::ClipboardProvider;
-//
-// #[cfg(target_os = "linux")]
-// fn main() {
-//     let mut ctx = X11ClipboardContext::<Primary>::new().unwrap();
-//
-//     let the_string = "Hello, world!";
-//
-//     ctx.set_contents(the_string.to_owned()).unwrap();
-// }
-//
-// #[cfg(not(target_os = "linux"))]
+#[cfg(all(target_os = "linux", feature = "x11"))]
+use copypasta::x11rb_clipboard::{Primary, X11RbClipboardContext};
+#[cfg(all(target_os = "linux", feature = "x11"))]
+use copypasta::ClipboardProvider;
+
+#[cfg(all(target_os = "linux", feature = "x11"))]
+fn main() {
+    let ctx = X11RbClipboardContext::<Primary>::new().unwrap();
+
+    let the_string = "Hello, world!";
+
+    ctx.set_contents(the_string.to_owned()).unwrap();
+
+    // The selection is only available for as long as we own it.
+    println!("Middle-click to paste {:?}, press enter to exit.", ctx.get_contents().unwrap());
+    std::io::stdin().read_line(&mut String::new()).unwrap();
+}
+
+#[cfg(not(all(target_os = "linux", feature = "x11")))]
 fn main() {
     println!("Primary selection is only available under linux!");
 }
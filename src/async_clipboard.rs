@@ -0,0 +1,298 @@
+// Copyright 2016 Avraham Weinstock
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+
+use crate::common::{ClipboardError, ClipboardProvider, ContentType, Result};
+
+/// Asynchronous counterpart of [`ClipboardProvider`].
+///
+/// The returned futures don't depend on any particular executor, and dropping one cancels its
+/// operation. The x11rb and Wayland backends implement this natively, any other provider can be
+/// wrapped in an [`AsyncClipboardContext`].
+pub trait AsyncClipboardProvider: Send + Sync {
+    /// Get the clipboard contents as a String.
+    fn get_contents(&self) -> ClipboardFuture<String>;
+    /// Set the clipboard contents as a String.
+    fn set_contents(&self, data: String) -> ClipboardFuture<()>;
+    /// Get the list of content types supported by the current clipboard item.
+    fn get_content_types(&self) -> ClipboardFuture<Vec<ContentType>>;
+    /// Get data for a particular content type.
+    fn get_content_for_type(&self, ct: &ContentType) -> ClipboardFuture<Vec<u8>>;
+    /// Set the mapping of content types to data in the clipboard.
+    fn set_content_types(&self, map: HashMap<ContentType, Vec<u8>>) -> ClipboardFuture<()>;
+}
+
+/// Result of an asynchronous clipboard operation.
+pub struct ClipboardFuture<T> {
+    future: Pin<Box<dyn Future<Output = Result<T>> + Send>>,
+}
+
+impl<T> ClipboardFuture<T> {
+    pub(crate) fn new<F>(future: F) -> Self
+    where
+        F: Future<Output = Result<T>> + Send + 'static,
+    {
+        Self { future: Box::pin(future) }
+    }
+}
+
+impl<T> Future for ClipboardFuture<T> {
+    type Output = Result<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<T>> {
+        self.future.as_mut().poll(cx)
+    }
+}
+
+/// Result of an operation completed by another thread.
+struct Slot<T> {
+    result: Option<Result<T>>,
+    waker: Option<Waker>,
+    cancelled: bool,
+    /// Lets the other thread know about the cancellation right away.
+    on_cancel: Option<Box<dyn FnOnce() + Send>>,
+}
+
+/// Receiving half of a [`Completer`].
+struct Receiver<T> {
+    slot: Arc<Mutex<Slot<T>>>,
+}
+
+impl<T> Future for Receiver<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<T>> {
+        let mut slot = self.slot.lock().unwrap();
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let on_cancel = {
+            let mut slot = self.slot.lock().unwrap();
+            slot.cancelled = true;
+            slot.on_cancel.take()
+        };
+
+        if let Some(on_cancel) = on_cancel {
+            on_cancel();
+        }
+    }
+}
+
+/// Sending half of a [`ClipboardFuture`] created by [`channel`].
+pub(crate) struct Completer<T> {
+    slot: Option<Arc<Mutex<Slot<T>>>>,
+}
+
+impl<T> Completer<T> {
+    pub(crate) fn is_cancelled(&self) -> bool {
+        match &self.slot {
+            Some(slot) => slot.lock().unwrap().cancelled,
+            None => true,
+        }
+    }
+
+    /// Run `on_cancel` once the future is dropped before completion.
+    #[cfg(all(
+        unix,
+        not(any(
+            target_os = "macos",
+            target_os = "android",
+            target_os = "ios",
+            target_os = "emscripten"
+        )),
+        feature = "wayland"
+    ))]
+    pub(crate) fn on_cancel<F>(&self, on_cancel: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if let Some(slot) = &self.slot {
+            let mut slot = slot.lock().unwrap();
+            if !slot.cancelled {
+                slot.on_cancel = Some(Box::new(on_cancel));
+            }
+        }
+    }
+
+    pub(crate) fn complete(mut self, result: Result<T>) {
+        if let Some(slot) = self.slot.take() {
+            let mut slot = slot.lock().unwrap();
+            slot.result = Some(result);
+            slot.on_cancel = None;
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        // The operation was lost, most likely because the context was dropped.
+        if let Some(slot) = self.slot.take() {
+            let mut slot = slot.lock().unwrap();
+            slot.result = Some(Err(ClipboardError::ConnectionLost));
+            slot.on_cancel = None;
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// Create a future which is completed from another thread.
+pub(crate) fn channel<T>() -> (Completer<T>, ClipboardFuture<T>)
+where
+    T: Send + 'static,
+{
+    let slot = Slot { result: None, waker: None, cancelled: false, on_cancel: None };
+    let slot = Arc::new(Mutex::new(slot));
+    (Completer { slot: Some(slot.clone()) }, ClipboardFuture::new(Receiver { slot }))
+}
+
+/// Run `future` to completion, parking the current thread while it is pending.
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    )),
+    any(feature = "x11", feature = "wayland")
+))]
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    use std::task::Wake;
+    use std::thread::Thread;
+
+    /// Waker unparking the blocked thread.
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let mut future = Box::pin(future);
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+type Job<C> = Box<dyn FnOnce(&C) + Send>;
+
+/// Asynchronous access to any clipboard.
+///
+/// The blocking context is moved to a thread of its own, which runs the operations one at a time
+/// in the order they were started. Dropping a future only cancels its operation if it hasn't
+/// started yet.
+pub struct AsyncClipboardContext<C> {
+    jobs: Mutex<Option<Sender<Job<C>>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<C> AsyncClipboardContext<C>
+where
+    C: ClipboardProvider + 'static,
+{
+    pub fn new(context: C) -> Result<Self> {
+        let (jobs, receiver) = mpsc::channel::<Job<C>>();
+        let thread = thread::Builder::new().name("copypasta-async".into()).spawn(move || {
+            for job in receiver {
+                job(&context);
+            }
+        })?;
+
+        Ok(Self { jobs: Mutex::new(Some(jobs)), thread: Some(thread) })
+    }
+
+    fn run<T, F>(&self, operation: F) -> ClipboardFuture<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&C) -> Result<T> + Send + 'static,
+    {
+        let (completer, future) = channel();
+        let job: Job<C> = Box::new(move |context| {
+            if !completer.is_cancelled() {
+                completer.complete(operation(context));
+            }
+        });
+
+        // If the thread is gone, the job is dropped along with the completer, failing the future.
+        if let Some(jobs) = self.jobs.lock().unwrap().as_ref() {
+            let _ = jobs.send(job);
+        }
+
+        future
+    }
+}
+
+impl<C> AsyncClipboardProvider for AsyncClipboardContext<C>
+where
+    C: ClipboardProvider + 'static,
+{
+    fn get_contents(&self) -> ClipboardFuture<String> {
+        self.run(|context| context.get_contents())
+    }
+
+    fn set_contents(&self, data: String) -> ClipboardFuture<()> {
+        self.run(move |context| context.set_contents(data))
+    }
+
+    fn get_content_types(&self) -> ClipboardFuture<Vec<ContentType>> {
+        self.run(|context| context.get_content_types())
+    }
+
+    fn get_content_for_type(&self, ct: &ContentType) -> ClipboardFuture<Vec<u8>> {
+        let ct = ct.clone();
+        self.run(move |context| context.get_content_for_type(&ct))
+    }
+
+    fn set_content_types(&self, map: HashMap<ContentType, Vec<u8>>) -> ClipboardFuture<()> {
+        self.run(move |context| context.set_content_types(map))
+    }
+}
+
+impl<C> Drop for AsyncClipboardContext<C> {
+    fn drop(&mut self) {
+        // Closing the channel stops the thread once the pending operations are done.
+        self.jobs.lock().unwrap().take();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,304 @@
+// Copyright 2016 Avraham Weinstock
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Clipboard access through external tools, like `wl-copy`/`wl-paste`, `xclip` and `xsel`.
+
+use std::collections::HashMap;
+use std::env;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::common::{ClipboardError, ClipboardProvider, ContentType, Namespace, Result};
+
+/// Placeholder replaced by the name of the content type in command templates.
+const TYPE_PLACEHOLDER: &str = "{type}";
+
+/// Targets which describe the selection, rather than provide a representation of its content.
+const META_TARGETS: [&str; 5] = ["TARGETS", "TIMESTAMP", "MULTIPLE", "SAVE_TARGETS", "DELETE"];
+
+/// Error messages of the paste tools when the clipboard is empty.
+const EMPTY_MESSAGES: [&str; 2] = ["Nothing is copied", "No selection"];
+
+/// Error messages of the paste tools when the requested content type is not offered.
+const UNAVAILABLE_MESSAGES: [&str; 2] = ["No suitable type of content copied", "not available"];
+
+/// Selection the tools operate on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Selection {
+    Clipboard,
+    Primary,
+}
+
+/// Command line templates for a clipboard tool.
+///
+/// Each template is a program followed by its arguments. In the templates handling content
+/// types, `{type}` is replaced by the name of the content type in `namespace`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Commands {
+    /// Copy text read from stdin.
+    pub copy: Vec<String>,
+    /// Paste text to stdout.
+    pub paste: Vec<String>,
+    /// Copy data of a content type read from stdin.
+    pub copy_type: Option<Vec<String>>,
+    /// Paste data of a content type to stdout.
+    pub paste_type: Option<Vec<String>>,
+    /// List the available content types on stdout, one per line.
+    pub list_types: Option<Vec<String>>,
+    /// Namespace of the content type names used by the tool.
+    pub namespace: Namespace,
+}
+
+impl Commands {
+    /// Commands for `wl-copy` and `wl-paste`, from wl-clipboard.
+    pub fn wl_clipboard(selection: Selection) -> Commands {
+        let primary: &[&str] = match selection {
+            Selection::Clipboard => &[],
+            Selection::Primary => &["--primary"],
+        };
+
+        Commands {
+            copy: template(&["wl-copy"], primary, &[]),
+            paste: template(&["wl-paste", "--no-newline"], primary, &[]),
+            copy_type: Some(template(&["wl-copy"], primary, &["--type", TYPE_PLACEHOLDER])),
+            paste_type: Some(template(&["wl-paste", "--no-newline"], primary, &[
+                "--type",
+                TYPE_PLACEHOLDER,
+            ])),
+            list_types: Some(template(&["wl-paste", "--list-types"], primary, &[])),
+            namespace: Namespace::Mime,
+        }
+    }
+
+    /// Commands for `xclip`.
+    pub fn xclip(selection: Selection) -> Commands {
+        let selection: &[&str] = match selection {
+            Selection::Clipboard => &["-selection", "clipboard"],
+            Selection::Primary => &["-selection", "primary"],
+        };
+
+        Commands {
+            copy: template(&["xclip"], selection, &["-in"]),
+            paste: template(&["xclip"], selection, &["-out"]),
+            copy_type: Some(template(&["xclip"], selection, &["-target", TYPE_PLACEHOLDER, "-in"])),
+            paste_type: Some(template(&["xclip"], selection, &[
+                "-target",
+                TYPE_PLACEHOLDER,
+                "-out",
+            ])),
+            list_types: Some(template(&["xclip"], selection, &["-target", "TARGETS", "-out"])),
+            namespace: Namespace::X11,
+        }
+    }
+
+    /// Commands for `xsel`, which only handles text.
+    pub fn xsel(selection: Selection) -> Commands {
+        let selection: &[&str] = match selection {
+            Selection::Clipboard => &["--clipboard"],
+            Selection::Primary => &["--primary"],
+        };
+
+        Commands {
+            copy: template(&["xsel"], selection, &["--input"]),
+            paste: template(&["xsel"], selection, &["--output"]),
+            copy_type: None,
+            paste_type: None,
+            list_types: None,
+            namespace: Namespace::X11,
+        }
+    }
+
+    /// Pick the tool for the display server in use, among those on the `PATH`.
+    ///
+    /// wl-clipboard is used under Wayland, and xclip or xsel under X11.
+    pub fn detect(selection: Selection) -> Option<Commands> {
+        if env::var_os("WAYLAND_DISPLAY").is_some() && on_path("wl-copy") && on_path("wl-paste") {
+            return Some(Commands::wl_clipboard(selection));
+        }
+
+        if env::var_os("DISPLAY").is_some() {
+            if on_path("xclip") {
+                return Some(Commands::xclip(selection));
+            } else if on_path("xsel") {
+                return Some(Commands::xsel(selection));
+            }
+        }
+
+        None
+    }
+}
+
+/// Clipboard accessed by running external tools.
+///
+/// This is a fallback for environments where the native backends don't work, but the command
+/// line tools do. Every operation spawns a process.
+pub struct CommandClipboardContext {
+    commands: Commands,
+}
+
+impl CommandClipboardContext {
+    /// Create a context for the clipboard, using the tools found by [`Commands::detect`].
+    pub fn new() -> Result<Self> {
+        let commands = Commands::detect(Selection::Clipboard).ok_or(ClipboardError::Unsupported)?;
+        Ok(Self::with_commands(commands))
+    }
+
+    /// Create a context running `commands`.
+    pub fn with_commands(commands: Commands) -> Self {
+        Self { commands }
+    }
+
+    /// Commands run by this context.
+    pub fn commands(&self) -> &Commands {
+        &self.commands
+    }
+}
+
+impl ClipboardProvider for CommandClipboardContext {
+    fn get_contents(&self) -> Result<String> {
+        Ok(String::from_utf8(paste(&self.commands.paste, None)?)?)
+    }
+
+    fn set_contents(&self, data: String) -> Result<()> {
+        self.set_text(&data)
+    }
+
+    fn set_text(&self, data: &str) -> Result<()> {
+        copy(&self.commands.copy, None, data.as_bytes())
+    }
+
+    fn get_content_types(&self) -> Result<Vec<ContentType>> {
+        let list_types = self.commands.list_types.as_ref().ok_or(ClipboardError::Unsupported)?;
+        let types = String::from_utf8(paste(list_types, None)?)?;
+
+        let mut cts = Vec::new();
+        for name in types.lines().map(str::trim) {
+            if name.is_empty() || META_TARGETS.contains(&name) {
+                continue;
+            }
+
+            // Several names may map to the same content type.
+            let ct = ContentType::from_name(self.commands.namespace, name);
+            if !cts.contains(&ct) {
+                cts.push(ct);
+            }
+        }
+        Ok(cts)
+    }
+
+    fn get_content_for_type(&self, ct: &ContentType) -> Result<Vec<u8>> {
+        let paste_type = self.commands.paste_type.as_ref().ok_or(ClipboardError::Unsupported)?;
+        paste(paste_type, Some(&ct.name(self.commands.namespace)))
+    }
+
+    /// Set the data for the richest of the content types.
+    ///
+    /// The tools take one content type per invocation, and each invocation replaces the
+    /// clipboard, so the other content types of the map are not written.
+    fn set_content_types(&self, map: HashMap<ContentType, Vec<u8>>) -> Result<()> {
+        let copy_type = self.commands.copy_type.as_ref().ok_or(ClipboardError::Unsupported)?;
+        let namespace = self.commands.namespace;
+        let (name, data) = map
+            .into_iter()
+            .map(|(ct, data)| (richness(&ct), ct.name(namespace), data))
+            .min()
+            .map(|(_, name, data)| (name, data))
+            .ok_or(ClipboardError::Unsupported)?;
+
+        copy(copy_type, Some(&name), &data)
+    }
+}
+
+/// Rank of a content type when picking the one to write, lower ranks being richer.
+///
+/// Text comes last, since every other representation carries more than it.
+fn richness(ct: &ContentType) -> u8 {
+    match ct {
+        ContentType::Png | ContentType::Pdf => 0,
+        ContentType::Html | ContentType::Rtf => 1,
+        ContentType::Url => 2,
+        ContentType::Custom(_) => 3,
+        ContentType::Text => 4,
+    }
+}
+
+fn template(program: &[&str], selection: &[&str], args: &[&str]) -> Vec<String> {
+    program.iter().chain(selection).chain(args).map(|arg| (*arg).to_owned()).collect()
+}
+
+/// Build the command for `template`, substituting the content type name.
+fn command(template: &[String], type_name: Option<&str>) -> Result<Command> {
+    let (program, args) = template.split_first().ok_or("empty command template")?;
+    let mut command = Command::new(program);
+    for arg in args {
+        match type_name {
+            Some(type_name) => command.arg(arg.replace(TYPE_PLACEHOLDER, type_name)),
+            None => command.arg(arg),
+        };
+    }
+    Ok(command)
+}
+
+fn copy(template: &[String], type_name: Option<&str>, data: &[u8]) -> Result<()> {
+    // The tools keep serving the clipboard from a background process, which would keep output
+    // pipes open, so their output is discarded.
+    let mut child = command(template, type_name)?
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let written = child.stdin.take().unwrap().write_all(data);
+    let status = child.wait()?;
+    written?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("`{}` failed with {}", template[0], status).into())
+    }
+}
+
+fn paste(template: &[String], type_name: Option<&str>) -> Result<Vec<u8>> {
+    let output = command(template, type_name)?.stdin(Stdio::null()).output()?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() {
+        Ok(output.stdout)
+    } else if EMPTY_MESSAGES.iter().any(|message| stderr.contains(message)) {
+        Err(ClipboardError::Empty)
+    } else if UNAVAILABLE_MESSAGES.iter().any(|message| stderr.contains(message)) {
+        Err(ClipboardError::FormatNotAvailable)
+    } else {
+        Err(format!("`{}` failed with {}: {}", template[0], output.status, stderr.trim()).into())
+    }
+}
+
+/// Check whether `program` is an executable in one of the directories of the `PATH`.
+fn on_path(program: &str) -> bool {
+    let paths = match env::var_os("PATH") {
+        Some(paths) => paths,
+        None => return false,
+    };
+
+    env::split_paths(&paths).any(|dir| is_executable(&dir.join(program)))
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
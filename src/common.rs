@@ -14,37 +14,276 @@
 
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::string::FromUtf8Error;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::RwLock;
+use std::time::Duration;
 
-pub type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync + 'static>>;
+use lazy_static::lazy_static;
+
+use crate::file_list::FileOperation;
+
+#[cfg(feature = "image-data")]
+use crate::image_data::ImageData;
+
+pub type Result<T> = std::result::Result<T, ClipboardError>;
+
+/// Errors returned by clipboard operations.
+#[derive(Debug)]
+pub enum ClipboardError {
+    /// The operation is not supported by this platform or backend.
+    Unsupported,
+    /// The clipboard has no contents.
+    Empty,
+    /// The clipboard has contents, but not in the requested format.
+    FormatNotAvailable,
+    /// The clipboard owner did not answer in time.
+    Timeout,
+    /// The connection to the clipboard was lost.
+    ConnectionLost,
+    /// The clipboard can only be used while the application has keyboard focus.
+    NoFocus,
+    /// Another client took the clipboard over while its contents were being set.
+    OwnershipRefused,
+    /// The clipboard contents are not valid UTF-8.
+    InvalidUtf8,
+    /// Any other error reported by the platform.
+    Backend(Box<dyn Error + Send + Sync + 'static>),
+}
+
+impl fmt::Display for ClipboardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClipboardError::Unsupported => f.write_str("unsupported for this platform"),
+            ClipboardError::Empty => f.write_str("clipboard is empty"),
+            ClipboardError::FormatNotAvailable => {
+                f.write_str("clipboard does not have data in the requested format")
+            },
+            ClipboardError::Timeout => f.write_str("timed out waiting for the clipboard"),
+            ClipboardError::ConnectionLost => f.write_str("connection to the clipboard was lost"),
+            ClipboardError::NoFocus => f.write_str("clipboard requires keyboard focus"),
+            ClipboardError::OwnershipRefused => {
+                f.write_str("clipboard was taken over by another client")
+            },
+            ClipboardError::InvalidUtf8 => f.write_str("clipboard contents are not valid UTF-8"),
+            ClipboardError::Backend(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for ClipboardError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ClipboardError::Backend(err) => Some(&**err),
+            _ => None,
+        }
+    }
+}
+
+impl From<FromUtf8Error> for ClipboardError {
+    fn from(_: FromUtf8Error) -> Self {
+        ClipboardError::InvalidUtf8
+    }
+}
+
+impl From<io::Error> for ClipboardError {
+    fn from(err: io::Error) -> Self {
+        ClipboardError::Backend(err.into())
+    }
+}
+
+#[cfg(feature = "image-data")]
+impl From<image::ImageError> for ClipboardError {
+    fn from(err: image::ImageError) -> Self {
+        ClipboardError::Backend(Box::new(err))
+    }
+}
+
+impl From<&str> for ClipboardError {
+    fn from(description: &str) -> Self {
+        ClipboardError::Backend(description.into())
+    }
+}
+
+impl From<String> for ClipboardError {
+    fn from(description: String) -> Self {
+        ClipboardError::Backend(description.into())
+    }
+}
 
 /// Trait for clipboard access
-pub trait ClipboardProvider: Send {
+///
+/// All methods take `&self`, and providers are `Send` and `Sync`, so a single context can be
+/// shared between threads, for example as an `Arc<dyn ClipboardProvider>`. Providers serialize
+/// concurrent operations internally.
+pub trait ClipboardProvider: Send + Sync {
     /// Method to get the clipboard contents as a String
     fn get_contents(&self) -> Result<String>;
     /// Method to set the clipboard contents as a String
     fn set_contents(&self, _: String) -> Result<()>;
+    /// Get the clipboard contents as a String, replacing invalid characters with U+FFFD instead
+    /// of failing.
+    fn get_contents_lossy(&self) -> Result<String> {
+        match self.get_contents() {
+            Err(ClipboardError::InvalidUtf8) => {
+                let data = self.get_content_for_type(&ContentType::Text)?;
+                Ok(String::from_utf8_lossy(&data).into_owned())
+            },
+            result => result,
+        }
+    }
+    /// Set the clipboard contents to borrowed text.
+    ///
+    /// Backends handing the text over to another program or the terminal write it without
+    /// copying it. The others keep the contents around, so they store a copy of it.
+    fn set_text(&self, text: &str) -> Result<()> {
+        self.set_contents(text.to_owned())
+    }
     /// Get the list of content types supported by the current clipboard item. Content types
-    /// are returned normalized.
+    /// are returned normalized, and the list is empty if the clipboard is.
     fn get_content_types(&self) -> Result<Vec<ContentType>> {
-        Err("unsupported for this platform".into())
+        Err(ClipboardError::Unsupported)
     }
     /// Get data for a particular content type
     fn get_content_for_type(&self, _ct: &ContentType) -> Result<Vec<u8>> {
-        Err("unsupported for this platform".into())
+        Err(ClipboardError::Unsupported)
     }
     /// Set the mapping of content types to data in the clipboard
     fn set_content_types(&self, _map: HashMap<ContentType, Vec<u8>>) -> Result<()> {
-        Err("unsupported for this platform".into())
+        Err(ClipboardError::Unsupported)
+    }
+    /// Get the HTML content of the clipboard.
+    fn get_html(&self) -> Result<String> {
+        Ok(String::from_utf8(self.get_content_for_type(&ContentType::Html)?)?)
+    }
+    /// Set the clipboard contents to HTML, along with a plain text alternative.
+    ///
+    /// Without `alt_text`, the alternative is generated by stripping the tags from `html`.
+    fn set_html(&self, html: &str, alt_text: Option<&str>) -> Result<()> {
+        let text = match alt_text {
+            Some(alt_text) => alt_text.to_owned(),
+            None => crate::html::to_plain_text(html),
+        };
+
+        let mut map = HashMap::new();
+        map.insert(ContentType::Html, html.as_bytes().to_vec());
+        map.insert(ContentType::Text, text.into_bytes());
+        self.set_content_types(map)
+    }
+    /// Get the files in the clipboard, from a URI list or GNOME's copied files.
+    fn get_file_list(&self) -> Result<Vec<PathBuf>> {
+        crate::file_list::get_file_list(self)
+    }
+    /// Get whether the files in the clipboard were copied or cut.
+    fn get_file_operation(&self) -> Result<FileOperation> {
+        crate::file_list::get_file_operation(self)
+    }
+    /// Set the clipboard contents to a list of files to copy.
+    fn set_file_list(&self, paths: &[PathBuf]) -> Result<()> {
+        crate::file_list::set_file_list(self, paths, FileOperation::Copy)
+    }
+    /// Set the clipboard contents to a list of files to copy or cut.
+    fn set_file_list_with_operation(
+        &self,
+        paths: &[PathBuf],
+        operation: FileOperation,
+    ) -> Result<()> {
+        crate::file_list::set_file_list(self, paths, operation)
+    }
+    /// Get the image in the clipboard, picking the best of the offered image formats.
+    #[cfg(feature = "image-data")]
+    fn get_image(&self) -> Result<ImageData> {
+        crate::image_data::get_image(self)
+    }
+    /// Set the clipboard contents to an image, encoded in each of the
+    /// [`image_content_types`](Self::image_content_types).
+    #[cfg(feature = "image-data")]
+    fn set_image(&self, image: ImageData) -> Result<()> {
+        crate::image_data::set_image(self, image)
+    }
+    /// Image formats stored by [`set_image`](Self::set_image), which are the ones applications on
+    /// the platform expect to find.
+    ///
+    /// Only PNG, BMP and JPEG content types can be encoded.
+    #[cfg(feature = "image-data")]
+    fn image_content_types(&self) -> Vec<ContentType> {
+        vec![ContentType::Png]
+    }
+    /// Start watching for changes of the clipboard contents.
+    ///
+    /// Events are delivered until the returned [`ClipboardWatch`] is dropped.
+    fn watch(&self) -> Result<ClipboardWatch> {
+        Err(ClipboardError::Unsupported)
     }
     /// Normalize a content type, ensuring it is not a [`ContentType::Custom`] instance if it
     /// can be represented as another member of [`ContentType`].
-    fn normalize_content_type(_ct: ContentType) -> ContentType {
-        todo!("not implemented for this platform")
+    ///
+    /// Names are looked up in the content type registry, as MIME types unless the platform uses
+    /// another [`Namespace`].
+    fn normalize_content_type(ct: ContentType) -> ContentType
+    where
+        Self: Sized,
+    {
+        ct.normalize(Namespace::Mime)
     }
     /// Denormalize content type. The resulting string can be used to create a
     /// [`ContentType::Custom`] instance.
-    fn denormalize_content_type(_ct: ContentType) -> String {
-        todo!("not implemented for this platform")
+    fn denormalize_content_type(ct: ContentType) -> String
+    where
+        Self: Sized,
+    {
+        ct.name(Namespace::Mime)
+    }
+}
+
+/// Change of the clipboard contents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClipboardEvent {
+    /// New contents were set, possibly by this process.
+    Changed,
+    /// The contents were removed, usually because the client owning them went away.
+    Cleared,
+}
+
+/// Stream of [`ClipboardEvent`]s, returned by [`ClipboardProvider::watch`].
+///
+/// Iterating blocks until the next event, and ends once the backend stops delivering events.
+pub struct ClipboardWatch {
+    events: Receiver<ClipboardEvent>,
+    /// Backend state which has to live as long as the clipboard is watched.
+    _guard: Box<dyn Send>,
+}
+
+impl ClipboardWatch {
+    pub(crate) fn new<G: Send + 'static>(events: Receiver<ClipboardEvent>, guard: G) -> Self {
+        Self { events, _guard: Box::new(guard) }
+    }
+
+    /// Get the next event, if one is pending.
+    pub fn try_next(&self) -> Option<ClipboardEvent> {
+        match self.events.try_recv() {
+            Ok(event) => Some(event),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+        }
+    }
+
+    /// Wait for the next event, giving up after `timeout`.
+    pub fn next_timeout(&self, timeout: Duration) -> Option<ClipboardEvent> {
+        match self.events.recv_timeout(timeout) {
+            Ok(event) => Some(event),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+        }
+    }
+}
+
+impl Iterator for ClipboardWatch {
+    type Item = ClipboardEvent;
+
+    fn next(&mut self) -> Option<ClipboardEvent> {
+        self.events.recv().ok()
     }
 }
 
@@ -63,3 +302,147 @@ pub enum ContentType {
     Url,
     Custom(String),
 }
+
+impl ContentType {
+    /// Content type called `name` in `namespace`.
+    ///
+    /// Platforms commonly mix naming schemes (X11 clients use MIME types as atom names, for
+    /// example), so names from other namespaces are recognized as well. Unknown names are
+    /// returned as [`ContentType::Custom`].
+    pub fn from_name(namespace: Namespace, name: &str) -> ContentType {
+        Self::lookup(Some(namespace), name)
+    }
+
+    /// Content type called `name` in any namespace.
+    ///
+    /// This is for names whose naming scheme isn't known, without preferring any namespace.
+    /// Registered names still take precedence over the builtin ones.
+    pub fn from_any_name(name: &str) -> ContentType {
+        Self::lookup(None, name)
+    }
+
+    fn lookup(namespace: Option<Namespace>, name: &str) -> ContentType {
+        let registry = CUSTOM_CONTENT_TYPES.read().unwrap();
+        let custom = registry.iter().map(|(ct, names)| (ct, names.as_array()));
+        let builtin = BUILTIN_CONTENT_TYPES.iter().map(|(ct, names)| (ct, *names));
+        let entries: Vec<_> = custom.chain(builtin).collect();
+
+        namespace
+            .and_then(|namespace| {
+                entries.iter().find(|(_, names)| names[namespace as usize] == name)
+            })
+            .or_else(|| entries.iter().find(|(_, names)| names.contains(&name)))
+            .map(|(ct, _)| (*ct).clone())
+            .unwrap_or_else(|| ContentType::Custom(name.to_owned()))
+    }
+
+    /// Name of this content type in `namespace`.
+    ///
+    /// Custom content types which were not registered are named verbatim.
+    pub fn name(&self, namespace: Namespace) -> String {
+        let registry = CUSTOM_CONTENT_TYPES.read().unwrap();
+        if let Some((_, names)) = registry.iter().find(|(ct, _)| ct == self) {
+            return names.get(namespace).to_owned();
+        }
+
+        match self {
+            ContentType::Custom(name) => name.clone(),
+            ct => BUILTIN_CONTENT_TYPES
+                .iter()
+                .find(|(builtin, _)| builtin == ct)
+                .map(|(_, names)| names[namespace as usize].to_owned())
+                .expect("every portable content type is registered"),
+        }
+    }
+
+    /// Normalize a [`ContentType::Custom`] named in `namespace`, leaving other types untouched.
+    pub fn normalize(self, namespace: Namespace) -> ContentType {
+        match self {
+            ContentType::Custom(name) => ContentType::from_name(namespace, &name),
+            ct => ct,
+        }
+    }
+}
+
+/// Naming scheme used by a platform to identify clipboard formats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Namespace {
+    /// MIME types, used by Wayland.
+    Mime,
+    /// Uniform type identifiers, used by macOS.
+    Uti,
+    /// Clipboard format names, used by Windows.
+    Windows,
+    /// Atom names, used by X11.
+    X11,
+}
+
+/// Names of a content type in every [`Namespace`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentTypeNames {
+    pub mime: String,
+    pub uti: String,
+    pub windows: String,
+    pub x11: String,
+}
+
+impl ContentTypeNames {
+    /// Name used in `namespace`.
+    pub fn get(&self, namespace: Namespace) -> &str {
+        match namespace {
+            Namespace::Mime => &self.mime,
+            Namespace::Uti => &self.uti,
+            Namespace::Windows => &self.windows,
+            Namespace::X11 => &self.x11,
+        }
+    }
+
+    fn as_array(&self) -> [&str; 4] {
+        [&self.mime, &self.uti, &self.windows, &self.x11]
+    }
+}
+
+/// Names of the portable content types, indexed by [`Namespace`].
+///
+/// When a content type has multiple entries, the first one is used to name it.
+const BUILTIN_CONTENT_TYPES: [(ContentType, [&str; 4]); 7] = [
+    (ContentType::Text, [
+        "text/plain;charset=utf-8",
+        "public.utf8-plain-text",
+        "CF_UNICODETEXT",
+        "UTF8_STRING",
+    ]),
+    (ContentType::Text, ["text/plain", "public.plain-text", "CF_TEXT", "text/plain"]),
+    (ContentType::Html, ["text/html", "public.html", "HTML Format", "text/html"]),
+    (ContentType::Pdf, [
+        "application/pdf",
+        "com.adobe.pdf",
+        "Portable Document Format",
+        "application/pdf",
+    ]),
+    (ContentType::Png, ["image/png", "public.png", "PNG", "image/png"]),
+    (ContentType::Rtf, ["text/rtf", "public.rtf", "Rich Text Format", "text/rtf"]),
+    (ContentType::Url, [
+        "text/uri-list",
+        "public.file-url",
+        "UniformResourceLocatorW",
+        "text/uri-list",
+    ]),
+];
+
+lazy_static! {
+    /// Content types registered by the application.
+    static ref CUSTOM_CONTENT_TYPES: RwLock<Vec<(ContentType, ContentTypeNames)>> =
+        RwLock::new(Vec::new());
+}
+
+/// Register the names of a content type with every backend.
+///
+/// This lets a [`ContentType::Custom`] stand for a format which is named differently on each
+/// platform. Registered names take precedence over the builtin ones, and registering a content
+/// type again replaces its names.
+pub fn register_content_type(ct: ContentType, names: ContentTypeNames) {
+    let mut registry = CUSTOM_CONTENT_TYPES.write().unwrap();
+    registry.retain(|(registered, _)| *registered != ct);
+    registry.push((ct, names));
+}
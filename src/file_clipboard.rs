@@ -0,0 +1,246 @@
+// Copyright 2016 Avraham Weinstock
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, DirBuilder, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+use crate::common::{ClipboardError, ClipboardProvider, ContentType, Namespace, Result};
+
+/// Name of the lock file in each selection's directory.
+const LOCK_FILE: &str = ".lock";
+
+/// Selection stored by the context, each in a directory of its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Selection {
+    Clipboard,
+    Primary,
+}
+
+impl Selection {
+    fn directory_name(self) -> &'static str {
+        match self {
+            Selection::Clipboard => "clipboard",
+            Selection::Primary => "primary",
+        }
+    }
+}
+
+/// Clipboard stored in the file system, with one file per content type.
+///
+/// All contexts using the same directory share the clipboard, even across processes, which
+/// makes this a stand-in for the system clipboard in containers and CI. Access is synchronized
+/// with `flock`, so readers never see a partially replaced clipboard.
+pub struct FileClipboardContext {
+    root: PathBuf,
+    selection: Selection,
+}
+
+impl FileClipboardContext {
+    /// Create a context for the clipboard stored under `$XDG_RUNTIME_DIR/copypasta`.
+    ///
+    /// Without `XDG_RUNTIME_DIR`, a directory private to the user in the temporary directory is
+    /// used instead. Since anyone can create it first, this fails if it already exists but isn't
+    /// owned by the user or is accessible to others.
+    pub fn new() -> Result<Self> {
+        let root = match env::var_os("XDG_RUNTIME_DIR") {
+            Some(runtime_dir) => PathBuf::from(runtime_dir).join("copypasta"),
+            None => private_temp_dir()?,
+        };
+        Ok(Self::with_directory(root))
+    }
+
+    /// Create a context for the clipboard stored under `root`.
+    pub fn with_directory<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into(), selection: Selection::Clipboard }
+    }
+
+    /// Use `selection` instead of the clipboard.
+    pub fn with_selection(mut self, selection: Selection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Directory holding the contents of this context's selection.
+    pub fn directory(&self) -> PathBuf {
+        self.root.join(self.selection.directory_name())
+    }
+
+    /// Remove the contents of this context's selection.
+    pub fn clear(&self) -> Result<()> {
+        let directory = self.directory();
+        let _lock = Lock::acquire(&directory, libc::LOCK_EX)?;
+        remove_contents(&directory)
+    }
+
+    /// Replace the contents of the selection.
+    fn store(&self, contents: HashMap<ContentType, Vec<u8>>) -> Result<()> {
+        let directory = self.directory();
+        let _lock = Lock::acquire(&directory, libc::LOCK_EX)?;
+
+        remove_contents(&directory)?;
+        for (ct, data) in contents {
+            fs::write(directory.join(file_name(&ct)), data)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ClipboardProvider for FileClipboardContext {
+    fn get_contents(&self) -> Result<String> {
+        Ok(String::from_utf8(self.get_content_for_type(&ContentType::Text)?)?)
+    }
+
+    fn set_contents(&self, data: String) -> Result<()> {
+        let mut contents = HashMap::new();
+        contents.insert(ContentType::Text, data.into_bytes());
+        self.store(contents)
+    }
+
+    fn get_content_types(&self) -> Result<Vec<ContentType>> {
+        let directory = self.directory();
+        let _lock = Lock::acquire(&directory, libc::LOCK_SH)?;
+
+        let mut cts = content_files(&directory)?
+            .into_iter()
+            .filter_map(|(name, _)| decode_file_name(&name))
+            .map(|name| ContentType::from_name(Namespace::Mime, &name))
+            .collect::<Vec<_>>();
+        cts.sort_by_key(|ct| ct.name(Namespace::Mime));
+
+        Ok(cts)
+    }
+
+    fn get_content_for_type(&self, ct: &ContentType) -> Result<Vec<u8>> {
+        let directory = self.directory();
+        let _lock = Lock::acquire(&directory, libc::LOCK_SH)?;
+
+        match fs::read(directory.join(file_name(ct))) {
+            Ok(data) => Ok(data),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                if content_files(&directory)?.is_empty() {
+                    Err(ClipboardError::Empty)
+                } else {
+                    Err(ClipboardError::FormatNotAvailable)
+                }
+            },
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn set_content_types(&self, map: HashMap<ContentType, Vec<u8>>) -> Result<()> {
+        self.store(map)
+    }
+}
+
+/// Advisory lock on a selection's directory, released on drop.
+struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// Lock `directory`, creating it if necessary.
+    fn acquire(directory: &Path, operation: libc::c_int) -> Result<Self> {
+        DirBuilder::new().recursive(true).mode(0o700).create(directory)?;
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(directory.join(LOCK_FILE))?;
+
+        loop {
+            if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+                return Ok(Self { _file: file });
+            }
+
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err.into());
+            }
+        }
+    }
+}
+
+/// Create the fallback root in the shared temporary directory, or check that the existing one
+/// can't be read or planted with content by other users.
+fn private_temp_dir() -> Result<PathBuf> {
+    let uid = unsafe { libc::getuid() };
+    let root = env::temp_dir().join(format!("copypasta-{}", uid));
+
+    match DirBuilder::new().mode(0o700).create(&root) {
+        Err(err) if err.kind() != io::ErrorKind::AlreadyExists => return Err(err.into()),
+        _ => (),
+    }
+
+    // Symlinks are not followed, so they can't point us at somebody else's directory.
+    let metadata = fs::symlink_metadata(&root)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        let root = root.display();
+        return Err(format!("{} is not a directory private to the current user", root).into());
+    }
+
+    Ok(root)
+}
+
+/// Files of a selection's directory holding content, along with their paths.
+fn content_files(directory: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name != LOCK_FILE {
+            files.push((name, entry.path()));
+        }
+    }
+    Ok(files)
+}
+
+fn remove_contents(directory: &Path) -> Result<()> {
+    for (_, path) in content_files(directory)? {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Name of the file for a content type, its percent-encoded MIME type.
+fn file_name(ct: &ContentType) -> String {
+    let mut name = String::new();
+    for byte in ct.name(Namespace::Mime).bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'+' => name.push(byte as char),
+            _ => name.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    name
+}
+
+fn decode_file_name(name: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut iter = name.bytes();
+    while let Some(byte) = iter.next() {
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+
+        let hex = [iter.next()?, iter.next()?];
+        bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+    }
+    String::from_utf8(bytes).ok()
+}
@@ -0,0 +1,172 @@
+// Copyright 2016 Avraham Weinstock
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use crate::common::{ClipboardError, ClipboardProvider, ContentType, Result};
+
+/// Content type used by GNOME and most other file managers to tell copied files from cut ones.
+const GNOME_COPIED_FILES: &str = "x-special/gnome-copied-files";
+
+/// What should happen to the files in the clipboard once they are pasted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FileOperation {
+    Copy,
+    Cut,
+}
+
+pub(crate) fn get_file_list<P: ClipboardProvider + ?Sized>(provider: &P) -> Result<Vec<PathBuf>> {
+    let offered = provider.get_content_types()?;
+
+    if offered.contains(&ContentType::Url) {
+        let uri_list = String::from_utf8(provider.get_content_for_type(&ContentType::Url)?)?;
+        return Ok(uri_list
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(from_uri)
+            .collect());
+    }
+
+    let gnome_copied_files = ContentType::Custom(GNOME_COPIED_FILES.into());
+    if offered.contains(&gnome_copied_files) {
+        let copied_files = String::from_utf8(provider.get_content_for_type(&gnome_copied_files)?)?;
+        return Ok(copied_files.lines().skip(1).filter_map(from_uri).collect());
+    }
+
+    Err(ClipboardError::FormatNotAvailable)
+}
+
+pub(crate) fn get_file_operation<P: ClipboardProvider + ?Sized>(
+    provider: &P,
+) -> Result<FileOperation> {
+    let gnome_copied_files = ContentType::Custom(GNOME_COPIED_FILES.into());
+    let copied_files = match provider.get_content_for_type(&gnome_copied_files) {
+        Ok(copied_files) => copied_files,
+        // Plain URI lists are always copied.
+        Err(ClipboardError::FormatNotAvailable) => {
+            provider.get_content_for_type(&ContentType::Url)?;
+            return Ok(FileOperation::Copy);
+        },
+        Err(err) => return Err(err),
+    };
+
+    if copied_files.split(|&b| b == b'\n').next() == Some(b"cut") {
+        Ok(FileOperation::Cut)
+    } else {
+        Ok(FileOperation::Copy)
+    }
+}
+
+pub(crate) fn set_file_list<P: ClipboardProvider + ?Sized>(
+    provider: &P,
+    paths: &[PathBuf],
+    operation: FileOperation,
+) -> Result<()> {
+    let uris = paths.iter().map(|path| to_uri(path)).collect::<Result<Vec<_>>>()?;
+
+    let mut uri_list = String::new();
+    let mut copied_files = String::from(match operation {
+        FileOperation::Copy => "copy",
+        FileOperation::Cut => "cut",
+    });
+    for uri in &uris {
+        // URI lists use CRLF line endings, as required by RFC 2483.
+        uri_list.push_str(uri);
+        uri_list.push_str("\r\n");
+        copied_files.push('\n');
+        copied_files.push_str(uri);
+    }
+    let text: Vec<_> = paths.iter().map(|path| path.to_string_lossy()).collect();
+
+    let mut map = HashMap::new();
+    map.insert(ContentType::Url, uri_list.into_bytes());
+    map.insert(ContentType::Custom(GNOME_COPIED_FILES.into()), copied_files.into_bytes());
+    map.insert(ContentType::Text, text.join("\n").into_bytes());
+    provider.set_content_types(map)
+}
+
+/// Convert a path to a percent-encoded `file://` URI, resolving it against the current
+/// directory if it is relative.
+fn to_uri(path: &Path) -> Result<String> {
+    let path = if path.is_absolute() { path.to_owned() } else { env::current_dir()?.join(path) };
+
+    let mut uri = String::from("file://");
+    for &byte in path_bytes(&path).iter() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            },
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    Ok(uri)
+}
+
+/// Convert a `file://` URI to a path, ignoring URIs of other schemes or hosts.
+fn from_uri(uri: &str) -> Option<PathBuf> {
+    let uri = uri.trim_end_matches('\r');
+    let rest = uri.strip_prefix("file://")?;
+    let path = match rest.find('/') {
+        Some(0) => rest,
+        Some(start) if &rest[..start] == "localhost" => &rest[start..],
+        _ => return None,
+    };
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+    while let Some(byte) = iter.next() {
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+
+        let hex = [iter.next()?, iter.next()?];
+        let hex = std::str::from_utf8(&hex).ok()?;
+        bytes.push(u8::from_str_radix(hex, 16).ok()?);
+    }
+
+    path_from_bytes(bytes)
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Vec<u8> {
+    // `C:\dir\file` becomes `/C:/dir/file`.
+    let path = path.to_string_lossy().replace('\\', "/");
+    let path = if path.starts_with('/') { path } else { format!("/{}", path) };
+    path.into_bytes()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::unix::ffi::OsStringExt;
+
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    let path = String::from_utf8(bytes).ok()?;
+    Some(PathBuf::from(path.trim_start_matches('/').replace('/', "\\")))
+}
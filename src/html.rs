@@ -0,0 +1,180 @@
+// Copyright 2016 Avraham Weinstock
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Elements which start on a new line.
+const BLOCK_ELEMENTS: [&str; 24] = [
+    "address",
+    "article",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "tr",
+    "ul",
+];
+
+/// Elements whose content is not displayed.
+const HIDDEN_ELEMENTS: [&str; 4] = ["head", "script", "style", "title"];
+
+/// Convert HTML to plain text, for use as a fallback by applications which don't accept HTML.
+///
+/// Tags are stripped, block elements are put on separate lines, runs of whitespace are collapsed
+/// and the common character references are decoded.
+pub(crate) fn to_plain_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+
+    loop {
+        let (content, tag_start) = match find_tag(rest) {
+            Some(start) => (&rest[..start], Some(start)),
+            None => (rest, None),
+        };
+        push_text(&mut text, &decode_entities(content));
+
+        let after = match tag_start {
+            Some(start) => &rest[start + 1..],
+            None => break,
+        };
+
+        if let Some(comment) = after.strip_prefix("!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        let (tag, remaining) = match after.find('>') {
+            Some(end) => (&after[..end], &after[end + 1..]),
+            None => (after, ""),
+        };
+        rest = remaining;
+
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+
+        if !closing && !tag.ends_with('/') && HIDDEN_ELEMENTS.contains(&name.as_str()) {
+            let end_tag = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&end_tag) {
+                Some(end) => rest[end..].find('>').map_or("", |close| &rest[end + close + 1..]),
+                None => "",
+            };
+        } else if BLOCK_ELEMENTS.contains(&name.as_str()) {
+            push_line_break(&mut text);
+        }
+    }
+
+    text.truncate(text.trim_end().len());
+    text
+}
+
+/// Position of the next `<` starting a tag or comment.
+///
+/// Like browsers, only a `<` followed by a tag name, `/` or `!` starts a tag, so text like `a < b`
+/// is kept. A tag which is never closed by a `>` is kept as text too.
+fn find_tag(html: &str) -> Option<usize> {
+    let last_close = html.rfind('>');
+    html.match_indices('<').map(|(start, _)| start).find(|&start| {
+        let after = &html[start + 1..];
+        let tag_name = after.starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!');
+        after.starts_with("!--") || (tag_name && last_close.map_or(false, |close| close > start))
+    })
+}
+
+/// Append text content, collapsing whitespace like a browser would.
+fn push_text(text: &mut String, content: &str) {
+    for c in content.chars() {
+        if c.is_ascii_whitespace() {
+            if !text.is_empty() && !text.ends_with(' ') && !text.ends_with('\n') {
+                text.push(' ');
+            }
+        } else {
+            text.push(c);
+        }
+    }
+}
+
+fn push_line_break(text: &mut String) {
+    text.truncate(text.trim_end_matches(' ').len());
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+/// Decode character references, leaving unknown ones untouched.
+fn decode_entities(content: &str) -> String {
+    let mut decoded = String::with_capacity(content.len());
+    let mut rest = content;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest[1..].find(';').and_then(|end| {
+            let c = decode_entity(&rest[1..end + 1])?;
+            Some((c, end + 2))
+        });
+        match entity {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+            },
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    let code = if let Some(hex) = entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+        u32::from_str_radix(hex, 16).ok()?
+    } else if let Some(decimal) = entity.strip_prefix('#') {
+        decimal.parse().ok()?
+    } else {
+        return match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => None,
+        };
+    };
+
+    std::char::from_u32(code)
+}
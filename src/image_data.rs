@@ -0,0 +1,111 @@
+// Copyright 2016 Avraham Weinstock
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::Cursor;
+
+use image::{ColorType, ImageFormat};
+
+use crate::common::{ClipboardError, ClipboardProvider, ContentType, Result};
+
+/// Decoded image, with 8 bits per channel RGBA pixels stored row by row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageData {
+    pub width: usize,
+    pub height: usize,
+    pub rgba: Vec<u8>,
+}
+
+/// Image content types, from the most preferred to the least preferred.
+///
+/// Formats without a builtin content type are listed under their name on every platform that
+/// uses one.
+fn image_types() -> [(ContentType, ImageFormat); 6] {
+    [
+        (ContentType::Png, ImageFormat::Png),
+        (ContentType::Custom("image/bmp".into()), ImageFormat::Bmp),
+        (ContentType::Custom("image/x-bmp".into()), ImageFormat::Bmp),
+        (ContentType::Custom("com.microsoft.bmp".into()), ImageFormat::Bmp),
+        (ContentType::Custom("image/jpeg".into()), ImageFormat::Jpeg),
+        (ContentType::Custom("public.jpeg".into()), ImageFormat::Jpeg),
+    ]
+}
+
+/// Read the best image format offered by the clipboard.
+pub(crate) fn get_image<P: ClipboardProvider + ?Sized>(provider: &P) -> Result<ImageData> {
+    let offered = provider.get_content_types()?;
+    load_image(&offered, |ct| provider.get_content_for_type(ct))?
+        .ok_or(ClipboardError::FormatNotAvailable)
+}
+
+/// Decode the best of the `offered` image formats, whose data is read with `load`.
+///
+/// Returns `None` if none of the offered content types is an image format.
+pub(crate) fn load_image<F>(offered: &[ContentType], load: F) -> Result<Option<ImageData>>
+where
+    F: FnOnce(&ContentType) -> Result<Vec<u8>>,
+{
+    let image_types = image_types();
+    let (ct, format) = match image_types.iter().find(|(ct, _)| offered.contains(ct)) {
+        Some(image_type) => image_type,
+        None => return Ok(None),
+    };
+
+    let data = load(ct)?;
+    let image = image::load_from_memory_with_format(&data, *format)?.into_rgba8();
+
+    Ok(Some(ImageData {
+        width: image.width() as usize,
+        height: image.height() as usize,
+        rgba: image.into_raw(),
+    }))
+}
+
+/// Store an image in the clipboard, encoded in every format the provider prefers.
+pub(crate) fn set_image<P: ClipboardProvider + ?Sized>(
+    provider: &P,
+    image: ImageData,
+) -> Result<()> {
+    if image.width.checked_mul(image.height).and_then(|pixels| pixels.checked_mul(4))
+        != Some(image.rgba.len())
+    {
+        return Err("image data does not match its dimensions".into());
+    }
+    let too_large = |_| ClipboardError::from("image is too large");
+    let width = u32::try_from(image.width).map_err(too_large)?;
+    let height = u32::try_from(image.height).map_err(too_large)?;
+
+    let image_types = image_types();
+    let mut map = HashMap::new();
+    for ct in provider.image_content_types() {
+        let format = match image_types.iter().find(|(image_type, _)| *image_type == ct) {
+            Some((_, format)) => *format,
+            None => return Err(format!("cannot encode images as {:?}", ct).into()),
+        };
+
+        let mut data = Cursor::new(Vec::new());
+        image::write_buffer_with_format(
+            &mut data,
+            &image.rgba,
+            width,
+            height,
+            ColorType::Rgba8,
+            format,
+        )?;
+        map.insert(ct, data.into_inner());
+    }
+
+    provider.set_content_types(map)
+}
@@ -11,11 +11,68 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-#![deny(clippy::all, clippy::if_not_else, clippy::enum_glob_use, clippy::wrong_pub_self_convention)]
+#![deny(clippy::all, clippy::if_not_else, clippy::enum_glob_use)]
 
 mod common;
-pub use crate::common::{ClipboardProvider, ContentType, Result};
+pub use crate::async_clipboard::{AsyncClipboardContext, AsyncClipboardProvider, ClipboardFuture};
+pub use crate::common::{
+    register_content_type, ClipboardError, ClipboardEvent, ClipboardProvider, ClipboardWatch,
+    ContentType, ContentTypeNames, Namespace, Result,
+};
+pub use crate::file_list::FileOperation;
+#[cfg(feature = "image-data")]
+pub use crate::image_data::ImageData;
 
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    ))
+))]
+pub mod command_clipboard;
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    ))
+))]
+pub mod file_clipboard;
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    ))
+))]
+pub mod linux_clipboard;
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    ))
+))]
+pub mod osc52_clipboard;
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    ))
+))]
+pub mod tmux_clipboard;
 #[cfg(all(
     unix,
     not(any(
@@ -45,6 +102,12 @@ pub mod windows_clipboard;
 #[cfg(target_os = "macos")]
 pub mod osx_clipboard;
 
+pub mod async_clipboard;
+mod file_list;
+mod html;
+#[cfg(feature = "image-data")]
+mod image_data;
+pub mod memory_clipboard;
 pub mod nop_clipboard;
 
 #[cfg(all(
@@ -56,8 +119,7 @@ pub mod nop_clipboard;
         target_os = "emscripten"
     ))
 ))]
-#[cfg(feature = "x11")]
-pub type ClipboardContext = x11rb_clipboard::X11RbClipboardContext;
+pub type ClipboardContext = linux_clipboard::LinuxClipboardContext;
 #[cfg(windows)]
 pub type ClipboardContext = windows_clipboard::WindowsClipboardContext;
 #[cfg(target_os = "macos")]
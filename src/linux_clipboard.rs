@@ -0,0 +1,148 @@
+// Copyright 2017 Avraham Weinstock
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use crate::command_clipboard::CommandClipboardContext;
+use crate::common::{ClipboardProvider, ClipboardWatch, ContentType, Namespace, Result};
+#[cfg(feature = "image-data")]
+use crate::image_data::ImageData;
+use crate::nop_clipboard::NopClipboardContext;
+use crate::tmux_clipboard::TmuxClipboardContext;
+#[cfg(feature = "wayland")]
+use crate::wayland_clipboard;
+#[cfg(feature = "x11")]
+use crate::x11rb_clipboard::X11RbClipboardContext;
+
+/// Clipboard of the display server the process is running under, picked at runtime.
+///
+/// Wayland is used when `WAYLAND_DISPLAY` is set and the compositor supports the wlr data control
+/// protocol, which lets clients without a focused window use the clipboard. Otherwise, X11 (which
+/// also covers XWayland) is used, then external tools like `wl-copy` or `xclip`. Without a display
+/// server, tmux buffers are used inside tmux sessions, and [`NopClipboardContext`] anywhere else.
+pub enum LinuxClipboardContext {
+    #[cfg(feature = "wayland")]
+    Wayland(wayland_clipboard::Clipboard),
+    #[cfg(feature = "x11")]
+    X11(Box<X11RbClipboardContext>),
+    Command(CommandClipboardContext),
+    Tmux(TmuxClipboardContext),
+    Nop(NopClipboardContext),
+}
+
+impl LinuxClipboardContext {
+    pub fn new() -> Result<Self> {
+        #[cfg(feature = "wayland")]
+        {
+            if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+                if let Ok((_, clipboard)) = wayland_clipboard::Clipboard::connect() {
+                    return Ok(LinuxClipboardContext::Wayland(clipboard));
+                }
+            }
+        }
+
+        #[cfg(feature = "x11")]
+        {
+            if std::env::var_os("DISPLAY").is_some() {
+                if let Ok(context) = X11RbClipboardContext::new() {
+                    return Ok(LinuxClipboardContext::X11(Box::new(context)));
+                }
+            }
+        }
+
+        if let Ok(context) = CommandClipboardContext::new() {
+            return Ok(LinuxClipboardContext::Command(context));
+        }
+
+        if std::env::var_os("TMUX").is_some() {
+            return Ok(LinuxClipboardContext::Tmux(TmuxClipboardContext::new()?));
+        }
+
+        Ok(LinuxClipboardContext::Nop(NopClipboardContext::new()?))
+    }
+}
+
+/// Forward a call to the backend in use.
+macro_rules! dispatch {
+    ($context:expr, $backend:ident => $call:expr) => {
+        match $context {
+            #[cfg(feature = "wayland")]
+            LinuxClipboardContext::Wayland($backend) => $call,
+            #[cfg(feature = "x11")]
+            LinuxClipboardContext::X11($backend) => $call,
+            LinuxClipboardContext::Command($backend) => $call,
+            LinuxClipboardContext::Tmux($backend) => $call,
+            LinuxClipboardContext::Nop($backend) => $call,
+        }
+    };
+}
+
+impl ClipboardProvider for LinuxClipboardContext {
+    fn get_contents(&self) -> Result<String> {
+        dispatch!(self, backend => backend.get_contents())
+    }
+
+    fn set_contents(&self, data: String) -> Result<()> {
+        dispatch!(self, backend => backend.set_contents(data))
+    }
+
+    fn set_text(&self, text: &str) -> Result<()> {
+        dispatch!(self, backend => backend.set_text(text))
+    }
+
+    fn get_contents_lossy(&self) -> Result<String> {
+        dispatch!(self, backend => backend.get_contents_lossy())
+    }
+
+    fn get_content_types(&self) -> Result<Vec<ContentType>> {
+        dispatch!(self, backend => backend.get_content_types())
+    }
+
+    fn get_content_for_type(&self, ct: &ContentType) -> Result<Vec<u8>> {
+        dispatch!(self, backend => backend.get_content_for_type(ct))
+    }
+
+    fn set_content_types(&self, map: HashMap<ContentType, Vec<u8>>) -> Result<()> {
+        dispatch!(self, backend => backend.set_content_types(map))
+    }
+
+    #[cfg(feature = "image-data")]
+    fn get_image(&self) -> Result<ImageData> {
+        dispatch!(self, backend => backend.get_image())
+    }
+
+    #[cfg(feature = "image-data")]
+    fn image_content_types(&self) -> Vec<ContentType> {
+        dispatch!(self, backend => backend.image_content_types())
+    }
+
+    fn watch(&self) -> Result<ClipboardWatch> {
+        dispatch!(self, backend => backend.watch())
+    }
+
+    /// Names are looked up in every namespace, since the backend is only picked at runtime.
+    fn normalize_content_type(ct: ContentType) -> ContentType {
+        match ct {
+            ContentType::Custom(name) => ContentType::from_any_name(&name),
+            ct => ct,
+        }
+    }
+
+    /// Content types are named by their MIME type, which every backend understands (X11 clients
+    /// use them as atom names as well). Use the function of the concrete backend for its own
+    /// naming scheme, like `UTF8_STRING` on X11.
+    fn denormalize_content_type(ct: ContentType) -> String {
+        ct.name(Namespace::Mime)
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright 2016 Avraham Weinstock
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+
+use crate::common::{
+    ClipboardError, ClipboardEvent, ClipboardProvider, ClipboardWatch, ContentType, Result,
+};
+
+/// Slot of the in-memory clipboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Selection {
+    Clipboard,
+    Primary,
+}
+
+/// Data stored for each content type, in no particular order.
+type Contents = Vec<(ContentType, Vec<u8>)>;
+
+/// State shared by all clones of a context.
+#[derive(Default)]
+struct Shared {
+    slots: HashMap<Selection, Contents>,
+    watchers: Vec<(Selection, Sender<ClipboardEvent>)>,
+}
+
+impl Shared {
+    fn notify(&mut self, selection: Selection, event: ClipboardEvent) {
+        // Watches which were dropped are forgotten.
+        self.watchers
+            .retain(|(watched, sender)| *watched != selection || sender.send(event).is_ok());
+    }
+}
+
+/// Clipboard which keeps its contents in memory, without involving the system.
+///
+/// Clones share their contents, so copying in one context can be observed by pasting in another.
+/// This makes it a stand-in for the system clipboard in tests.
+#[derive(Clone)]
+pub struct InMemoryClipboardContext {
+    shared: Arc<Mutex<Shared>>,
+    selection: Selection,
+}
+
+impl InMemoryClipboardContext {
+    /// Create an empty clipboard, using the [`Selection::Clipboard`] slot.
+    pub fn new() -> Result<InMemoryClipboardContext> {
+        Ok(InMemoryClipboardContext {
+            shared: Arc::new(Mutex::new(Shared::default())),
+            selection: Selection::Clipboard,
+        })
+    }
+
+    /// Context sharing the contents of this one, but using the `selection` slot.
+    pub fn with_selection(&self, selection: Selection) -> InMemoryClipboardContext {
+        InMemoryClipboardContext { shared: self.shared.clone(), selection }
+    }
+
+    /// Slot used by this context.
+    pub fn selection(&self) -> Selection {
+        self.selection
+    }
+
+    /// Remove the contents of this context's slot.
+    pub fn clear(&self) {
+        let mut shared = self.shared.lock().unwrap();
+        if shared.slots.remove(&self.selection).is_some() {
+            shared.notify(self.selection, ClipboardEvent::Cleared);
+        }
+    }
+
+    fn store(&self, contents: Contents) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.slots.insert(self.selection, contents);
+        shared.notify(self.selection, ClipboardEvent::Changed);
+    }
+}
+
+impl ClipboardProvider for InMemoryClipboardContext {
+    fn get_contents(&self) -> Result<String> {
+        let data = self.get_content_for_type(&ContentType::Text)?;
+        Ok(String::from_utf8(data)?)
+    }
+
+    fn set_contents(&self, data: String) -> Result<()> {
+        self.store(vec![(ContentType::Text, data.into_bytes())]);
+        Ok(())
+    }
+
+    fn get_content_types(&self) -> Result<Vec<ContentType>> {
+        let shared = self.shared.lock().unwrap();
+        let contents = shared.slots.get(&self.selection).map(Vec::as_slice).unwrap_or_default();
+        Ok(contents.iter().map(|(ct, _)| ct.clone()).collect())
+    }
+
+    fn get_content_for_type(&self, ct: &ContentType) -> Result<Vec<u8>> {
+        // Stored content types are normalized, so the requested one must be too.
+        let ct = Self::normalize_content_type(ct.clone());
+
+        let shared = self.shared.lock().unwrap();
+        let contents = shared.slots.get(&self.selection).ok_or(ClipboardError::Empty)?;
+        contents
+            .iter()
+            .find(|(stored, _)| *stored == ct)
+            .map(|(_, data)| data.clone())
+            .ok_or(ClipboardError::FormatNotAvailable)
+    }
+
+    fn set_content_types(&self, map: HashMap<ContentType, Vec<u8>>) -> Result<()> {
+        let contents =
+            map.into_iter().map(|(ct, data)| (Self::normalize_content_type(ct), data)).collect();
+        self.store(contents);
+        Ok(())
+    }
+
+    fn watch(&self) -> Result<ClipboardWatch> {
+        let (sender, receiver) = mpsc::channel();
+        self.shared.lock().unwrap().watchers.push((self.selection, sender));
+        Ok(ClipboardWatch::new(receiver, ()))
+    }
+}
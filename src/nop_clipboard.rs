@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::common::{ClipboardProvider, Result};
+use crate::common::{ClipboardError, ClipboardProvider, Result};
 
 pub struct NopClipboardContext;
 
@@ -28,7 +28,7 @@ impl ClipboardProvider for NopClipboardContext {
             "Attempting to get the contents of the clipboard, which hasn't yet been implemented \
              on this platform."
         );
-        Err("not implemented".into())
+        Err(ClipboardError::Unsupported)
     }
 
     fn set_contents(&self, _: String) -> Result<()> {
@@ -36,6 +36,6 @@ impl ClipboardProvider for NopClipboardContext {
             "Attempting to set the contents of the clipboard, which hasn't yet been implemented \
              on this platform."
         );
-        Err("not implemented".into())
+        Err(ClipboardError::Unsupported)
     }
 }
@@ -0,0 +1,326 @@
+// Copyright 2016 Avraham Weinstock
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Clipboard access through the terminal, using the OSC 52 escape sequence.
+//!
+//! The terminal emulator owns the clipboard, so this works wherever the terminal does, including
+//! over SSH and inside containers. Reading requires the terminal to answer OSC 52 queries, which
+//! many terminals disable for security reasons.
+
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::mem::MaybeUninit;
+use std::os::unix::io::AsRawFd;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::common::{ClipboardError, ClipboardProvider, Result};
+
+/// Time to wait for the terminal to answer a query, unless configured otherwise.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Longest escape sequence screen accepts, so sequences are split into chunks of this size.
+const SCREEN_CHUNK_SIZE: usize = 76;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Selection targeted by the escape sequences.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Selection {
+    Clipboard,
+    Primary,
+}
+
+impl Selection {
+    fn parameter(self) -> &'static str {
+        match self {
+            Selection::Clipboard => "c",
+            Selection::Primary => "p",
+        }
+    }
+}
+
+/// Terminal multiplexer between us and the terminal emulator.
+///
+/// Multiplexers don't forward OSC 52 by themselves, so the sequences are wrapped in a DCS
+/// passthrough sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Passthrough {
+    None,
+    Tmux,
+    Screen,
+}
+
+impl Passthrough {
+    /// Detect the multiplexer from the environment.
+    pub fn detect() -> Passthrough {
+        if env::var_os("TMUX").is_some() {
+            Passthrough::Tmux
+        } else if env::var("TERM").ok().filter(|term| term.starts_with("screen")).is_some() {
+            Passthrough::Screen
+        } else {
+            Passthrough::None
+        }
+    }
+
+    fn wrap(self, sequence: &str) -> String {
+        match self {
+            Passthrough::None => sequence.to_owned(),
+            Passthrough::Tmux => {
+                format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+            },
+            Passthrough::Screen => sequence
+                .as_bytes()
+                .chunks(SCREEN_CHUNK_SIZE)
+                .map(|chunk| format!("\x1bP{}\x1b\\", String::from_utf8_lossy(chunk)))
+                .collect(),
+        }
+    }
+}
+
+/// Clipboard of the terminal emulator, accessed through OSC 52 escape sequences.
+pub struct Osc52ClipboardContext {
+    terminal: Mutex<File>,
+    /// Input received while waiting for answers, which was not part of them.
+    input: Mutex<Vec<u8>>,
+    selection: Selection,
+    passthrough: Passthrough,
+    timeout: Duration,
+}
+
+impl Osc52ClipboardContext {
+    /// Create a context for the controlling terminal, detecting multiplexers from the
+    /// environment.
+    pub fn new() -> Result<Self> {
+        let terminal = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        Ok(Self::with_terminal(terminal))
+    }
+
+    /// Create a context for `terminal`, such as a pty.
+    pub fn with_terminal(terminal: File) -> Self {
+        Self {
+            terminal: Mutex::new(terminal),
+            input: Mutex::new(Vec::new()),
+            selection: Selection::Clipboard,
+            passthrough: Passthrough::detect(),
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Use `selection` instead of the clipboard.
+    pub fn with_selection(mut self, selection: Selection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Override the detected multiplexer.
+    pub fn with_passthrough(mut self, passthrough: Passthrough) -> Self {
+        self.passthrough = passthrough;
+        self
+    }
+
+    /// Wait `timeout` for the terminal to answer a query, instead of one second.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Take the input received from the terminal while waiting for the answers to queries.
+    ///
+    /// Reading the clipboard consumes everything the terminal sends until its answer arrives,
+    /// including keys typed in the meantime. Everything but the answer is kept here, so
+    /// applications reading from the same terminal can still process it.
+    pub fn take_input(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.input.lock().unwrap())
+    }
+
+    /// Read the answer to a query, which looks like `ESC ] 52 ; c ; <base64> BEL`.
+    ///
+    /// Terminals which don't support queries don't answer at all, so this fails with a timeout.
+    fn read_answer(&self, terminal: &mut File) -> Result<Vec<u8>> {
+        let mut input = Vec::new();
+        let answer = self.receive_answer(terminal, &mut input);
+        self.input.lock().unwrap().extend_from_slice(&input);
+        answer
+    }
+
+    /// Read from the terminal into `input` until the answer arrived, then remove it from `input`.
+    fn receive_answer(&self, terminal: &mut File, input: &mut Vec<u8>) -> Result<Vec<u8>> {
+        let prefix = format!("\x1b]52;{};", self.selection.parameter());
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(start) = find(input, prefix.as_bytes()) {
+                let data_start = start + prefix.len();
+                if let Some((data_len, terminator_len)) = find_terminator(&input[data_start..]) {
+                    let answer: Vec<u8> =
+                        input.drain(start..data_start + data_len + terminator_len).collect();
+                    return base64_decode(&answer[prefix.len()..prefix.len() + data_len]);
+                }
+            }
+
+            let now = Instant::now();
+            if now >= deadline || !poll_readable(terminal, deadline - now)? {
+                return Err(ClipboardError::Timeout);
+            }
+
+            let mut buffer = [0; 1024];
+            match terminal.read(&mut buffer)? {
+                0 => return Err(ClipboardError::ConnectionLost),
+                n => input.extend_from_slice(&buffer[..n]),
+            }
+        }
+    }
+}
+
+impl ClipboardProvider for Osc52ClipboardContext {
+    fn get_contents(&self) -> Result<String> {
+        let mut terminal = self.terminal.lock().unwrap();
+        let _raw_mode = RawMode::enable(&terminal)?;
+
+        let query = format!("\x1b]52;{};?\x07", self.selection.parameter());
+        terminal.write_all(self.passthrough.wrap(&query).as_bytes())?;
+        terminal.flush()?;
+
+        let data = self.read_answer(&mut terminal)?;
+        if data.is_empty() {
+            return Err(ClipboardError::Empty);
+        }
+
+        Ok(String::from_utf8(data)?)
+    }
+
+    fn set_contents(&self, data: String) -> Result<()> {
+        self.set_text(&data)
+    }
+
+    fn set_text(&self, data: &str) -> Result<()> {
+        let sequence = format!(
+            "\x1b]52;{};{}\x07",
+            self.selection.parameter(),
+            base64_encode(data.as_bytes())
+        );
+
+        let mut terminal = self.terminal.lock().unwrap();
+        terminal.write_all(self.passthrough.wrap(&sequence).as_bytes())?;
+        terminal.flush()?;
+        Ok(())
+    }
+}
+
+/// Terminal put in non-canonical mode without echo, so the answer to a query can be read without
+/// waiting for a newline or showing up on the screen. The previous mode is restored on drop.
+struct RawMode {
+    fd: libc::c_int,
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable(terminal: &File) -> io::Result<Self> {
+        let fd = terminal.as_raw_fd();
+        let mut termios = MaybeUninit::uninit();
+        if unsafe { libc::tcgetattr(fd, termios.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let original = unsafe { termios.assume_init() };
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { fd, original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.original) };
+    }
+}
+
+/// Wait until `terminal` has input, returning `false` once `timeout` expires.
+fn poll_readable(terminal: &File, timeout: Duration) -> io::Result<bool> {
+    let mut fd = libc::pollfd { fd: terminal.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+    let timeout = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+    match unsafe { libc::poll(&mut fd, 1, timeout) } {
+        -1 => {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                Ok(true)
+            } else {
+                Err(err)
+            }
+        },
+        0 => Ok(false),
+        _ => Ok(true),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Find the end of the data of an answer, returning its length and the one of the terminator.
+///
+/// Answers end with BEL or ST (`ESC \`). Both bytes of ST are needed before the answer is
+/// complete, so the backslash doesn't end up as input.
+fn find_terminator(data: &[u8]) -> Option<(usize, usize)> {
+    let end = data.iter().position(|&b| b == b'\x07' || b == b'\x1b')?;
+    match (data[end], data.get(end + 1)) {
+        (b'\x07', _) => Some((end, 1)),
+        (_, Some(b'\\')) => Some((end, 2)),
+        (_, Some(_)) => Some((end, 1)),
+        (_, None) => None,
+    }
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let group = u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(BASE64_ALPHABET[(group >> (18 - 6 * i)) as usize & 0x3f] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn base64_decode(encoded: &[u8]) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(encoded.len() / 4 * 3);
+    let mut group = 0u32;
+    let mut bits = 0;
+    for &c in encoded.iter().filter(|&&c| c != b'=') {
+        let value = BASE64_ALPHABET
+            .iter()
+            .position(|&a| a == c)
+            .ok_or("invalid base64 in OSC 52 answer")?;
+        group = group << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            data.push((group >> bits) as u8);
+        }
+    }
+    Ok(data)
+}
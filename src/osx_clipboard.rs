@@ -98,13 +98,17 @@ impl ClipboardProvider for OSXClipboardContext {
             Id::from_ptr(obj)
         };
         if string_array.count() == 0 {
-            Err("pasteboard#readObjectsForClasses:options: returned empty".into())
+            Err(ClipboardError::Empty)
         } else {
             Ok(string_array[0].as_str().to_owned())
         }
     }
 
     fn set_contents(&self, data: String) -> Result<()> {
+        self.set_text(&data)
+    }
+
+    fn set_text(&self, data: &str) -> Result<()> {
         let lock = CLIPBOARD_CONTEXT_MUTEX.lock();
         if !lock.is_ok() {
             panic!("could not acquire mutex");
@@ -132,7 +136,11 @@ impl ClipboardProvider for OSXClipboardContext {
             let types: *mut NSArray<NSString> = msg_send![first_item.unwrap(), types];
             Id::from_ptr(types)
         };
-        Ok(types.enumerator().into_iter().map(|t| t.as_str().into()).collect())
+        Ok(types
+            .enumerator()
+            .into_iter()
+            .map(|t| ContentType::from_name(Namespace::Uti, t.as_str()))
+            .collect())
     }
 
     fn get_content_for_type(&self, ct: &ContentType) -> Result<Vec<u8>> {
@@ -142,13 +150,13 @@ impl ClipboardProvider for OSXClipboardContext {
         }
         let first_item = self.first_item(&mut lock.unwrap());
         if first_item.is_none() {
-            return Err("clipboard is empty".into());
+            return Err(ClipboardError::Empty);
         }
         let typ: Id<NSString> = ct.into();
         let data: Id<NSData> = unsafe {
             let data: *mut NSData = msg_send![self.pasteboard, dataForType: typ];
             if data.is_null() {
-                return Err(format!("clipboard does not have data for {:?}", &ct).into());
+                return Err(ClipboardError::FormatNotAvailable);
             }
             Id::from_ptr(data)
         };
@@ -183,53 +191,20 @@ impl ClipboardProvider for OSXClipboardContext {
     }
 
     fn normalize_content_type(ct: ContentType) -> ContentType {
-        match &ct {
-            ContentType::Custom(s) => s.into(),
-            _ => ct,
-        }
+        ct.normalize(Namespace::Uti)
     }
 
     fn denormalize_content_type(ct: ContentType) -> String {
-        match ct {
-            ContentType::Url => "public.file-url",
-            ContentType::Html => "public.html",
-            ContentType::Pdf => "com.adobe.pdf",
-            ContentType::Png => "public.png",
-            ContentType::Rtf => "public.rtf",
-            ContentType::Text => "public.utf8-plain-text",
-            ContentType::Custom(s) => return s,
-        }
-        .into()
+        ct.name(Namespace::Uti)
     }
 }
 
-// This is stolen from
-// https://github.com/ryanmcgrath/cacao/blob/87eda8f94af8c15eef5e2e3386764407f21d53eb/src/pasteboard/types.rs#L83-L103
-//
-// TODO: I think it should be possible to access values from the appropriate struct instead of
-//       hardcoding them here?
-//       https://developer.apple.com/documentation/appkit/nspasteboard/pasteboardtype
-//       I'm not really sure how this works though. Do I need some sort of bindgen?
 impl<'a> From<&'a ContentType> for Id<NSString> {
     fn from(pboard_type: &'a ContentType) -> Self {
         NSString::from_str(&OSXClipboardContext::denormalize_content_type(pboard_type.clone()))
     }
 }
 
-impl<T: AsRef<str>> From<T> for ContentType {
-    fn from(t: T) -> Self {
-        match t.as_ref() {
-            "public.file-url" => ContentType::Url,
-            "public.html" => ContentType::Html,
-            "com.adobe.pdf" => ContentType::Pdf,
-            "public.png" => ContentType::Png,
-            "public.rtf" => ContentType::Rtf,
-            "public.utf8-plain-text" => ContentType::Text,
-            other => ContentType::Custom(other.to_owned()),
-        }
-    }
-}
-
 // this is a convenience function that both cocoa-rs and
 //  glutin define, which seems to depend on the fact that
 //  Option::None has the same representation as a null pointer
@@ -0,0 +1,100 @@
+// Copyright 2016 Avraham Weinstock
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::common::{ClipboardError, ClipboardProvider, Result};
+
+/// Clipboard backed by the paste buffers of tmux.
+///
+/// Data is passed through `tmux load-buffer -` and `tmux save-buffer -`, which don't alter it in
+/// any way. The tmux server is the one of the current session when `$TMUX` is set, or the
+/// default one otherwise.
+pub struct TmuxClipboardContext {
+    buffer: Option<String>,
+}
+
+impl TmuxClipboardContext {
+    /// Create a context for the most recent buffer.
+    ///
+    /// Copying creates a new automatically named buffer, like copy mode does.
+    pub fn new() -> Result<Self> {
+        Ok(Self { buffer: None })
+    }
+
+    /// Create a context for the buffer called `name`.
+    pub fn with_buffer<S: Into<String>>(name: S) -> Self {
+        Self { buffer: Some(name.into()) }
+    }
+
+    /// Name of the buffer used by this context, `None` for the most recent one.
+    pub fn buffer(&self) -> Option<&str> {
+        self.buffer.as_deref()
+    }
+
+    fn command(&self, subcommand: &str) -> Command {
+        let mut command = Command::new("tmux");
+        command.arg(subcommand);
+        if let Some(buffer) = &self.buffer {
+            command.arg("-b").arg(buffer);
+        }
+        command.arg("-");
+        command
+    }
+}
+
+impl ClipboardProvider for TmuxClipboardContext {
+    fn get_contents(&self) -> Result<String> {
+        let output = self.command("save-buffer").stdin(Stdio::null()).output()?;
+
+        if output.status.success() {
+            return Ok(String::from_utf8(output.stdout)?);
+        }
+
+        // tmux reports "no buffers" or "no buffer <name>".
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.starts_with("no buffer") {
+            Err(ClipboardError::Empty)
+        } else {
+            Err(format!("`tmux save-buffer` failed with {}: {}", output.status, stderr.trim())
+                .into())
+        }
+    }
+
+    fn set_contents(&self, data: String) -> Result<()> {
+        self.set_text(&data)
+    }
+
+    fn set_text(&self, data: &str) -> Result<()> {
+        let mut child = self
+            .command("load-buffer")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        let written = child.stdin.take().unwrap().write_all(data.as_bytes());
+        let output = child.wait_with_output()?;
+        written?;
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            Err(format!("`tmux load-buffer` failed with {}: {}", output.status, stderr.trim())
+                .into())
+        }
+    }
+}
@@ -12,53 +12,379 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashMap;
+use std::error::Error;
 use std::ffi::c_void;
-use std::sync::{Arc, Mutex};
+use std::fmt;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
 
-use smithay_clipboard::Clipboard as WaylandClipboard;
+use sctk::reexports::client::Display;
 
-use crate::common::{ClipboardProvider, Result};
+use crate::async_clipboard::{block_on, AsyncClipboardProvider, ClipboardFuture};
+use crate::common::{ClipboardError, ClipboardProvider, ClipboardWatch, ContentType, Result};
+
+use self::worker::{Selection, Worker};
+
+mod data_control;
+mod watch;
+mod worker;
+
+/// MIME types used for text, in order of preference.
+const TEXT_MIME_TYPES: [&str; 3] = ["text/plain;charset=utf-8", "UTF8_STRING", "text/plain"];
+
+/// Time to wait for the selection owner to send data, unless configured otherwise.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Worker shared by a [`Primary`] and [`Clipboard`] pair, or the reason it failed to start.
+type SharedWorker = Arc<std::result::Result<Mutex<Worker>, Arc<ClipboardError>>>;
 
 pub struct Clipboard {
-    context: Arc<Mutex<WaylandClipboard>>,
+    handle: Handle,
 }
 
 pub struct Primary {
-    context: Arc<Mutex<WaylandClipboard>>,
+    handle: Handle,
 }
 
 /// Create new clipboard from a raw display pointer.
 ///
+/// The clipboards can be used while one of the surfaces of the display has keyboard focus, or at
+/// any time if the compositor supports the wlr data control protocol. If the clipboard thread
+/// fails to start, every operation reports the error instead.
+///
 /// # Safety
 ///
 /// Since the type of the display is a raw pointer, it's the responsibility of the callee to make
 /// sure that the passed pointer is a valid Wayland display.
 pub unsafe fn create_clipboards_from_external(display: *mut c_void) -> (Primary, Clipboard) {
-    let context = Arc::new(Mutex::new(WaylandClipboard::new(display)));
+    let display = Display::from_external_display(display as *mut _);
+    create_clipboards(Worker::spawn(display, false))
+}
 
-    (Primary { context: context.clone() }, Clipboard { context })
+fn create_clipboards(worker: Result<Worker>) -> (Primary, Clipboard) {
+    let worker = Arc::new(worker.map(Mutex::new).map_err(Arc::new));
+    let primary = Primary { handle: Handle::new(worker.clone(), Selection::Primary) };
+    let clipboard = Clipboard { handle: Handle::new(worker, Selection::Clipboard) };
+    (primary, clipboard)
+}
+
+impl Clipboard {
+    /// Create new clipboards with their own connection to the compositor.
+    ///
+    /// The display is picked from the environment, like any other Wayland client does. The
+    /// connection is closed once both the [`Primary`] and the [`Clipboard`] are dropped.
+    ///
+    /// Without a surface, the connection never gets keyboard focus, so the selections are used
+    /// through the wlr data control protocol. This fails with [`ClipboardError::Unsupported`] on
+    /// compositors which don't implement it, like GNOME's.
+    pub fn connect() -> Result<(Primary, Clipboard)> {
+        let display =
+            Display::connect_to_env().map_err(|err| ClipboardError::Backend(err.into()))?;
+        Ok(create_clipboards(Ok(Worker::spawn(display, true)?)))
+    }
+
+    /// Set how long to wait for the selection owner to send data.
+    ///
+    /// Defaults to 3 seconds.
+    pub fn set_timeout(&self, timeout: Duration) {
+        self.handle.set_timeout(timeout);
+    }
+}
+
+impl Primary {
+    /// Set how long to wait for the selection owner to send data.
+    ///
+    /// Defaults to 3 seconds.
+    pub fn set_timeout(&self, timeout: Duration) {
+        self.handle.set_timeout(timeout);
+    }
 }
 
 impl ClipboardProvider for Clipboard {
     fn get_contents(&self) -> Result<String> {
-        Ok(self.context.lock().unwrap().load()?)
+        block_on(self.handle.request().get_contents())
     }
 
     fn set_contents(&self, data: String) -> Result<()> {
-        self.context.lock().unwrap().store(data);
+        block_on(self.handle.request().set_contents(data))
+    }
+
+    fn get_content_types(&self) -> Result<Vec<ContentType>> {
+        block_on(self.handle.request().get_content_types::<Self>())
+    }
 
-        Ok(())
+    fn get_content_for_type(&self, ct: &ContentType) -> Result<Vec<u8>> {
+        block_on(self.handle.request().get_content_for_type::<Self>(ct.clone()))
+    }
+
+    fn set_content_types(&self, map: HashMap<ContentType, Vec<u8>>) -> Result<()> {
+        block_on(self.handle.request().set_content_types::<Self>(map))
+    }
+
+    fn watch(&self) -> Result<ClipboardWatch> {
+        self.handle.watch()
     }
 }
 
 impl ClipboardProvider for Primary {
     fn get_contents(&self) -> Result<String> {
-        Ok(self.context.lock().unwrap().load_primary()?)
+        block_on(self.handle.request().get_contents())
     }
 
     fn set_contents(&self, data: String) -> Result<()> {
-        self.context.lock().unwrap().store_primary(data);
+        block_on(self.handle.request().set_contents(data))
+    }
+
+    fn get_content_types(&self) -> Result<Vec<ContentType>> {
+        block_on(self.handle.request().get_content_types::<Self>())
+    }
+
+    fn get_content_for_type(&self, ct: &ContentType) -> Result<Vec<u8>> {
+        block_on(self.handle.request().get_content_for_type::<Self>(ct.clone()))
+    }
+
+    fn set_content_types(&self, map: HashMap<ContentType, Vec<u8>>) -> Result<()> {
+        block_on(self.handle.request().set_content_types::<Self>(map))
+    }
+
+    fn watch(&self) -> Result<ClipboardWatch> {
+        self.handle.watch()
+    }
+}
+
+/// Reads are received by the worker thread along with the other events, and dropping their future
+/// closes the pipe right away.
+impl AsyncClipboardProvider for Clipboard {
+    fn get_contents(&self) -> ClipboardFuture<String> {
+        let request = self.handle.request();
+        ClipboardFuture::new(async move { request.get_contents().await })
+    }
+
+    fn set_contents(&self, data: String) -> ClipboardFuture<()> {
+        let request = self.handle.request();
+        ClipboardFuture::new(async move { request.set_contents(data).await })
+    }
+
+    fn get_content_types(&self) -> ClipboardFuture<Vec<ContentType>> {
+        let request = self.handle.request();
+        ClipboardFuture::new(async move { request.get_content_types::<Self>().await })
+    }
+
+    fn get_content_for_type(&self, ct: &ContentType) -> ClipboardFuture<Vec<u8>> {
+        let (request, ct) = (self.handle.request(), ct.clone());
+        ClipboardFuture::new(async move { request.get_content_for_type::<Self>(ct).await })
+    }
+
+    fn set_content_types(&self, map: HashMap<ContentType, Vec<u8>>) -> ClipboardFuture<()> {
+        let request = self.handle.request();
+        ClipboardFuture::new(async move { request.set_content_types::<Self>(map).await })
+    }
+}
+
+impl AsyncClipboardProvider for Primary {
+    fn get_contents(&self) -> ClipboardFuture<String> {
+        let request = self.handle.request();
+        ClipboardFuture::new(async move { request.get_contents().await })
+    }
+
+    fn set_contents(&self, data: String) -> ClipboardFuture<()> {
+        let request = self.handle.request();
+        ClipboardFuture::new(async move { request.set_contents(data).await })
+    }
+
+    fn get_content_types(&self) -> ClipboardFuture<Vec<ContentType>> {
+        let request = self.handle.request();
+        ClipboardFuture::new(async move { request.get_content_types::<Self>().await })
+    }
+
+    fn get_content_for_type(&self, ct: &ContentType) -> ClipboardFuture<Vec<u8>> {
+        let (request, ct) = (self.handle.request(), ct.clone());
+        ClipboardFuture::new(async move { request.get_content_for_type::<Self>(ct).await })
+    }
+
+    fn set_content_types(&self, map: HashMap<ContentType, Vec<u8>>) -> ClipboardFuture<()> {
+        let request = self.handle.request();
+        ClipboardFuture::new(async move { request.set_content_types::<Self>(map).await })
+    }
+}
+
+/// Selection of a shared worker, implementing both [`Clipboard`] and [`Primary`].
+struct Handle {
+    worker: SharedWorker,
+    selection: Selection,
+    timeout: Mutex<Duration>,
+}
+
+impl Handle {
+    fn new(worker: SharedWorker, selection: Selection) -> Self {
+        Self { worker, selection, timeout: Mutex::new(DEFAULT_TIMEOUT) }
+    }
+
+    fn set_timeout(&self, timeout: Duration) {
+        *self.timeout.lock().unwrap() = timeout;
+    }
+
+    /// Start an operation, which keeps the current settings until it is done.
+    fn request(&self) -> Request {
+        let timeout = *self.timeout.lock().unwrap();
+        Request { worker: self.worker.clone(), selection: self.selection, timeout }
+    }
+
+    fn watch(&self) -> Result<ClipboardWatch> {
+        let (sender, receiver) = mpsc::channel();
+        let watch = self.request().worker()?.watch(self.selection, sender);
+        block_on(watch)?;
+
+        // The worker has to keep running for as long as the clipboard is watched.
+        Ok(ClipboardWatch::new(receiver, self.worker.clone()))
+    }
+}
+
+/// Single operation on the selection of a shared worker.
+///
+/// The worker is only locked to send the request, so operations run concurrently.
+struct Request {
+    worker: SharedWorker,
+    selection: Selection,
+    timeout: Duration,
+}
+
+impl Request {
+    fn worker(&self) -> Result<MutexGuard<'_, Worker>> {
+        match &*self.worker {
+            Ok(worker) => Ok(worker.lock().unwrap()),
+            Err(err) => Err(shared_error(err)),
+        }
+    }
+
+    async fn load(&self, mime_types: Vec<String>) -> Result<(String, Vec<u8>)> {
+        let load = self.worker()?.load(self.selection, mime_types, self.timeout);
+        load.await
+    }
+
+    async fn store(&self, mime_types: HashMap<String, Vec<u8>>) -> Result<()> {
+        let store = self.worker()?.store(self.selection, mime_types);
+        store.await
+    }
+
+    async fn get_contents(&self) -> Result<String> {
+        let data = self.load_text().await?;
+        Ok(String::from_utf8(data)?)
+    }
+
+    async fn set_contents(&self, data: String) -> Result<()> {
+        let mut mime_types = HashMap::new();
+        insert_text_mime_types(&mut mime_types, data.into_bytes());
+        self.store(mime_types).await
+    }
+
+    async fn get_content_types<P: ClipboardProvider>(&self) -> Result<Vec<ContentType>> {
+        let mime_types = self.worker()?.mime_types(self.selection);
+        let mut cts = Vec::new();
+        for mime_type in mime_types.await? {
+            // Several MIME types may map to the same content type.
+            let ct = P::normalize_content_type(ContentType::Custom(mime_type));
+            if !cts.contains(&ct) {
+                cts.push(ct);
+            }
+        }
+        Ok(cts)
+    }
+
+    async fn get_content_for_type<P: ClipboardProvider>(&self, ct: ContentType) -> Result<Vec<u8>> {
+        if ct == ContentType::Text {
+            return self.load_text().await;
+        }
+
+        let mime_type = P::denormalize_content_type(ct);
+        let (_, data) = self.load(vec![mime_type]).await?;
+        Ok(data)
+    }
+
+    async fn set_content_types<P: ClipboardProvider>(
+        &self,
+        map: HashMap<ContentType, Vec<u8>>,
+    ) -> Result<()> {
+        let mut mime_types = HashMap::new();
+        for (ct, data) in map {
+            if ct == ContentType::Text {
+                insert_text_mime_types(&mut mime_types, data);
+            } else {
+                mime_types.insert(P::denormalize_content_type(ct), data);
+            }
+        }
+
+        self.store(mime_types).await
+    }
+
+    /// Read the selection as text, using the most preferred text MIME type on offer.
+    async fn load_text(&self) -> Result<Vec<u8>> {
+        let mime_types = TEXT_MIME_TYPES.iter().map(|mime_type| (*mime_type).to_owned()).collect();
+        let (mime_type, mut data) = self.load(mime_types).await?;
+
+        // Clients offering `UTF8_STRING` commonly use X11 style line endings.
+        if mime_type == "UTF8_STRING" {
+            data = normalize_line_endings(data);
+        }
+
+        Ok(data)
+    }
+}
+
+/// Error reported by every operation of a worker which failed to start.
+///
+/// Backend errors can't be cloned, so they are wrapped instead.
+fn shared_error(err: &Arc<ClipboardError>) -> ClipboardError {
+    match **err {
+        ClipboardError::Unsupported => ClipboardError::Unsupported,
+        ClipboardError::Empty => ClipboardError::Empty,
+        ClipboardError::FormatNotAvailable => ClipboardError::FormatNotAvailable,
+        ClipboardError::Timeout => ClipboardError::Timeout,
+        ClipboardError::ConnectionLost => ClipboardError::ConnectionLost,
+        ClipboardError::NoFocus => ClipboardError::NoFocus,
+        ClipboardError::OwnershipRefused => ClipboardError::OwnershipRefused,
+        ClipboardError::InvalidUtf8 => ClipboardError::InvalidUtf8,
+        ClipboardError::Backend(_) => ClipboardError::Backend(Box::new(SharedError(err.clone()))),
+    }
+}
+
+/// Backend error shared by several operations.
+#[derive(Debug)]
+struct SharedError(Arc<ClipboardError>);
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl Error for SharedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// Offer `data` for every text MIME type.
+fn insert_text_mime_types(mime_types: &mut HashMap<String, Vec<u8>>, data: Vec<u8>) {
+    for mime_type in &TEXT_MIME_TYPES {
+        mime_types.insert((*mime_type).to_owned(), data.clone());
+    }
+}
 
-        Ok(())
+/// Convert CRLF and lone CR line endings to LF.
+fn normalize_line_endings(data: Vec<u8>) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(data.len());
+    let mut bytes = data.into_iter().peekable();
+    while let Some(byte) = bytes.next() {
+        if byte == b'\r' {
+            if bytes.peek() != Some(&b'\n') {
+                normalized.push(b'\n');
+            }
+        } else {
+            normalized.push(byte);
+        }
     }
+    normalized
 }
@@ -0,0 +1,197 @@
+//! Selections of windowless clients, through the wlr data control protocol.
+//!
+//! Regular clients can only use the selections while one of their surfaces has keyboard focus,
+//! which tools without a window never get. Compositors implementing
+//! `zwlr_data_control_manager_v1`, like the wlroots based ones and KDE, let any client read and
+//! set the selections instead, without focus or input serials.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::FromRawFd;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use sctk::reexports::client::protocol::wl_seat::WlSeat;
+use sctk::reexports::client::Attached;
+use sctk::reexports::protocols::wlr::unstable::data_control::v1::client::zwlr_data_control_device_v1::{
+    Event as DeviceEvent, ZwlrDataControlDeviceV1,
+};
+use sctk::reexports::protocols::wlr::unstable::data_control::v1::client::zwlr_data_control_manager_v1::ZwlrDataControlManagerV1;
+use sctk::reexports::protocols::wlr::unstable::data_control::v1::client::zwlr_data_control_offer_v1::{
+    Event as OfferEvent, ZwlrDataControlOfferV1,
+};
+use sctk::reexports::protocols::wlr::unstable::data_control::v1::client::zwlr_data_control_source_v1::Event as SourceEvent;
+
+use super::watch;
+use super::worker::{self, Selection};
+use crate::common::{ClipboardError, Result};
+
+/// Version of the device which added the primary selection.
+const PRIMARY_SELECTION_SINCE: u32 = 2;
+
+/// Data control device of a seat.
+pub struct DataControlDevice {
+    device: ZwlrDataControlDeviceV1,
+    offers: Rc<RefCell<Offers>>,
+}
+
+/// Offer along with the MIME types it announced.
+struct Offer {
+    offer: ZwlrDataControlOfferV1,
+    mime_types: Rc<RefCell<Vec<String>>>,
+}
+
+/// Offers known to a device.
+#[derive(Default)]
+struct Offers {
+    /// Offers which were announced, but not selected yet.
+    pending: Vec<Offer>,
+    clipboard: Option<Offer>,
+    primary: Option<Offer>,
+}
+
+impl Offers {
+    /// Make the pending `offer` the selection, destroying the previous one.
+    fn select(&mut self, selection: Selection, offer: Option<&ZwlrDataControlOfferV1>) {
+        let index = offer.and_then(|offer| self.pending.iter().position(|o| o.offer == *offer));
+        let offer = index.map(|index| self.pending.remove(index));
+
+        let previous = match selection {
+            Selection::Clipboard => std::mem::replace(&mut self.clipboard, offer),
+            Selection::Primary => std::mem::replace(&mut self.primary, offer),
+        };
+        if let Some(previous) = previous {
+            previous.offer.destroy();
+        }
+    }
+
+    fn get(&self, selection: Selection) -> Option<&Offer> {
+        match selection {
+            Selection::Clipboard => self.clipboard.as_ref(),
+            Selection::Primary => self.primary.as_ref(),
+        }
+    }
+
+    fn destroy(&mut self) {
+        let offers = self.pending.drain(..).chain(self.clipboard.take()).chain(self.primary.take());
+        for offer in offers {
+            offer.offer.destroy();
+        }
+    }
+}
+
+impl DataControlDevice {
+    pub fn new(manager: &Attached<ZwlrDataControlManagerV1>, seat: &WlSeat) -> Self {
+        let device = manager.get_data_device(seat);
+        let offers = Rc::new(RefCell::new(Offers::default()));
+
+        let device_offers = offers.clone();
+        device.quick_assign(move |device, event, dispatch_data| {
+            let mut offers = device_offers.borrow_mut();
+            match event {
+                DeviceEvent::DataOffer { id } => {
+                    let mime_types = Rc::new(RefCell::new(Vec::new()));
+                    let offer_mime_types = mime_types.clone();
+                    id.quick_assign(move |_, event, _| {
+                        if let OfferEvent::Offer { mime_type } = event {
+                            offer_mime_types.borrow_mut().push(mime_type);
+                        }
+                    });
+                    offers.pending.push(Offer { offer: id.detach(), mime_types });
+                },
+                DeviceEvent::Selection { id } => {
+                    offers.select(Selection::Clipboard, id.as_ref());
+                    watch::notify(dispatch_data, Selection::Clipboard, id.is_some());
+                },
+                DeviceEvent::PrimarySelection { id } => {
+                    offers.select(Selection::Primary, id.as_ref());
+                    watch::notify(dispatch_data, Selection::Primary, id.is_some());
+                },
+                // The device can't be used anymore, for example because its seat went away.
+                DeviceEvent::Finished => {
+                    offers.destroy();
+                    device.destroy();
+                },
+                _ => (),
+            }
+        });
+
+        Self { device: device.detach(), offers }
+    }
+
+    /// Get the MIME types offered for `selection`, or `None` if it's empty.
+    pub fn mime_types(&self, selection: Selection) -> Option<Vec<String>> {
+        let offers = self.offers.borrow();
+        let offer = offers.get(selection)?;
+        let mime_types = offer.mime_types.borrow().clone();
+        Some(mime_types)
+    }
+
+    /// Ask the owner of `selection` to write the first of `mime_types` it offers to a pipe.
+    ///
+    /// Returns the MIME type which was picked along with the read end of the pipe.
+    pub fn receive(&self, selection: Selection, mime_types: &[String]) -> Result<(String, File)> {
+        let offers = self.offers.borrow();
+        let offer = offers.get(selection).ok_or(ClipboardError::Empty)?;
+        let mime_type = {
+            let offered = offer.mime_types.borrow();
+            mime_types.iter().find(|mime| offered.contains(mime)).cloned()
+        };
+        let mime_type = mime_type.ok_or(ClipboardError::FormatNotAvailable)?;
+
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let (read, write) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+        // The file descriptor is duplicated when the request is queued, so our end of the pipe can
+        // be closed right away.
+        offer.offer.receive(mime_type.clone(), fds[1]);
+        drop(write);
+
+        Ok((mime_type, read))
+    }
+
+    /// Take ownership of `selection`, offering data for every MIME type in `data`.
+    pub fn set_selection(
+        &self,
+        manager: &Attached<ZwlrDataControlManagerV1>,
+        selection: Selection,
+        data: HashMap<String, Vec<u8>>,
+    ) -> Result<()> {
+        let primary = selection == Selection::Primary;
+        if primary && self.device.as_ref().version() < PRIMARY_SELECTION_SINCE {
+            return Err(ClipboardError::Unsupported);
+        }
+
+        let source = manager.create_data_source();
+        for mime_type in data.keys() {
+            source.offer(mime_type.clone());
+        }
+
+        let data = Arc::new(data);
+        source.quick_assign(move |source, event, _| match event {
+            SourceEvent::Send { mime_type, fd } => {
+                worker::send_data(&data, mime_type, unsafe { File::from_raw_fd(fd) })
+            },
+            SourceEvent::Cancelled => source.destroy(),
+            _ => (),
+        });
+
+        if primary {
+            self.device.set_primary_selection(Some(&source));
+        } else {
+            self.device.set_selection(Some(&source));
+        }
+
+        Ok(())
+    }
+
+    pub fn release(self) {
+        self.offers.borrow_mut().destroy();
+        self.device.destroy();
+    }
+}
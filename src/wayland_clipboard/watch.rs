@@ -0,0 +1,164 @@
+//! Selection devices used to follow changes of the selections.
+//!
+//! The devices of smithay-client-toolkit don't report when the selection changes, so every seat
+//! gets a second set of devices which only listens to the `selection` events.
+//!
+//! Compositors only send these events to clients with keyboard focus, so changes made while
+//! another client is focused are reported once focus comes back.
+
+use std::sync::mpsc::Sender;
+
+use sctk::primary_selection::PrimarySelectionDeviceManager;
+use sctk::reexports::client::protocol::wl_data_device::{Event as DataDeviceEvent, WlDataDevice};
+use sctk::reexports::client::protocol::wl_data_device_manager::WlDataDeviceManager;
+use sctk::reexports::client::protocol::wl_data_offer::WlDataOffer;
+use sctk::reexports::client::protocol::wl_seat::WlSeat;
+use sctk::reexports::client::{Attached, DispatchData};
+use sctk::reexports::protocols::misc::gtk_primary_selection::client::gtk_primary_selection_device::{
+    Event as GtkDeviceEvent, GtkPrimarySelectionDevice,
+};
+use sctk::reexports::protocols::misc::gtk_primary_selection::client::gtk_primary_selection_offer::GtkPrimarySelectionOffer;
+use sctk::reexports::protocols::unstable::primary_selection::v1::client::zwp_primary_selection_device_v1::{
+    Event as ZwpDeviceEvent, ZwpPrimarySelectionDeviceV1,
+};
+use sctk::reexports::protocols::unstable::primary_selection::v1::client::zwp_primary_selection_offer_v1::ZwpPrimarySelectionOfferV1;
+use sctk::reexports::protocols::wlr::unstable::data_control::v1::client::zwlr_data_control_manager_v1::ZwlrDataControlManagerV1;
+
+use super::worker::{Seats, Selection};
+use crate::common::ClipboardEvent;
+
+/// Channels of the clipboard watches.
+#[derive(Default)]
+pub struct Watchers {
+    watchers: Vec<(Selection, Sender<ClipboardEvent>)>,
+}
+
+impl Watchers {
+    pub fn add(&mut self, selection: Selection, events: Sender<ClipboardEvent>) {
+        self.watchers.push((selection, events));
+    }
+
+    fn notify(&mut self, selection: Selection, event: ClipboardEvent) {
+        // Watches which were dropped are forgotten.
+        self.watchers
+            .retain(|(watched, events)| *watched != selection || events.send(event).is_ok());
+    }
+}
+
+/// Globals used to create the selection devices.
+#[derive(Default)]
+pub struct Managers {
+    pub data_device: Option<Attached<WlDataDeviceManager>>,
+    pub primary_selection: Option<PrimarySelectionDeviceManager>,
+    pub data_control: Option<Attached<ZwlrDataControlManagerV1>>,
+}
+
+/// Devices watching the selections of a seat.
+pub struct SelectionDevices {
+    data_device: Option<WlDataDevice>,
+    primary_selection: Option<PrimarySelectionDevice>,
+}
+
+enum PrimarySelectionDevice {
+    Zwp(ZwpPrimarySelectionDeviceV1),
+    Gtk(GtkPrimarySelectionDevice),
+}
+
+impl SelectionDevices {
+    pub fn new(seat: &WlSeat, managers: &Managers) -> Self {
+        let data_device = managers.data_device.as_ref().map(|manager| {
+            let device = manager.get_data_device(seat);
+            let mut offers = Offers::default();
+            device.quick_assign(move |_, event, dispatch_data| match event {
+                DataDeviceEvent::DataOffer { id } => offers.add(id.detach()),
+                DataDeviceEvent::Selection { id } => {
+                    offers.select(id.as_ref(), WlDataOffer::destroy);
+                    notify(dispatch_data, Selection::Clipboard, id.is_some());
+                },
+                _ => (),
+            });
+            device.detach()
+        });
+
+        let primary_selection = managers.primary_selection.as_ref().map(|manager| match manager {
+            PrimarySelectionDeviceManager::Zwp(manager) => {
+                let device = manager.get_device(seat);
+                let mut offers = Offers::default();
+                device.quick_assign(move |_, event, dispatch_data| match event {
+                    ZwpDeviceEvent::DataOffer { offer } => offers.add(offer.detach()),
+                    ZwpDeviceEvent::Selection { id } => {
+                        offers.select(id.as_ref(), ZwpPrimarySelectionOfferV1::destroy);
+                        notify(dispatch_data, Selection::Primary, id.is_some());
+                    },
+                    _ => (),
+                });
+                PrimarySelectionDevice::Zwp(device.detach())
+            },
+            PrimarySelectionDeviceManager::Gtk(manager) => {
+                let device = manager.get_device(seat);
+                let mut offers = Offers::default();
+                device.quick_assign(move |_, event, dispatch_data| match event {
+                    GtkDeviceEvent::DataOffer { offer } => offers.add(offer.detach()),
+                    GtkDeviceEvent::Selection { id } => {
+                        offers.select(id.as_ref(), GtkPrimarySelectionOffer::destroy);
+                        notify(dispatch_data, Selection::Primary, id.is_some());
+                    },
+                    _ => (),
+                });
+                PrimarySelectionDevice::Gtk(device.detach())
+            },
+        });
+
+        Self { data_device, primary_selection }
+    }
+
+    pub fn release(self) {
+        if let Some(device) = self.data_device {
+            if device.as_ref().version() >= 2 {
+                device.release();
+            }
+        }
+
+        match self.primary_selection {
+            Some(PrimarySelectionDevice::Zwp(device)) => device.destroy(),
+            Some(PrimarySelectionDevice::Gtk(device)) => device.destroy(),
+            None => (),
+        }
+    }
+}
+
+pub fn notify(mut dispatch_data: DispatchData, selection: Selection, present: bool) {
+    let event = if present { ClipboardEvent::Changed } else { ClipboardEvent::Cleared };
+    if let Some(seats) = dispatch_data.get::<Seats>() {
+        seats.watchers.notify(selection, event);
+    }
+}
+
+/// Offers announced to a device.
+///
+/// Only the selection is of interest, every other offer is destroyed once a selection arrives.
+struct Offers<O> {
+    offers: Vec<O>,
+}
+
+impl<O> Default for Offers<O> {
+    fn default() -> Self {
+        Self { offers: Vec::new() }
+    }
+}
+
+impl<O: PartialEq> Offers<O> {
+    fn add(&mut self, offer: O) {
+        self.offers.push(offer);
+    }
+
+    fn select(&mut self, selection: Option<&O>, destroy: fn(&O)) {
+        self.offers.retain(|offer| {
+            let selected = Some(offer) == selection;
+            if !selected {
+                destroy(offer);
+            }
+            selected
+        });
+    }
+}
@@ -0,0 +1,823 @@
+//! Thread handling the Wayland selections.
+//!
+//! Wayland objects are bound to the event queue they were created on, so all clipboard requests
+//! are forwarded to a dedicated thread which owns its own queue on the display.
+//!
+//! The selections are used through wlr data control when the compositor supports it, otherwise
+//! through the regular data devices, which only work while one of our surfaces has focus.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
+use std::os::unix::net::UnixStream;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use sctk::data_device::{
+    DataDevice, DataDeviceHandler, DataDeviceHandling, DataSourceEvent, DndEvent,
+};
+use sctk::environment::{Environment, SimpleGlobal};
+use sctk::primary_selection::{
+    PrimarySelectionDevice, PrimarySelectionDeviceManager, PrimarySelectionHandler,
+    PrimarySelectionHandling, PrimarySelectionSourceEvent,
+};
+use sctk::reexports::client::protocol::wl_data_device_manager::WlDataDeviceManager;
+use sctk::reexports::client::protocol::wl_keyboard::{Event as KeyboardEvent, WlKeyboard};
+use sctk::reexports::client::protocol::wl_pointer::{Event as PointerEvent, WlPointer};
+use sctk::reexports::client::protocol::wl_seat::WlSeat;
+use sctk::reexports::client::{Attached, DispatchData, Display, EventQueue};
+use sctk::reexports::protocols::wlr::unstable::data_control::v1::client::zwlr_data_control_manager_v1::ZwlrDataControlManagerV1;
+use sctk::seat::{self, SeatData, SeatHandler, SeatHandling, SeatListener};
+use sctk::MissingGlobal;
+
+use super::data_control::DataControlDevice;
+use super::watch::{Managers, SelectionDevices, Watchers};
+use crate::async_clipboard::{self, ClipboardFuture, Completer};
+use crate::common::{ClipboardError, ClipboardEvent, Result};
+
+/// Size of the buffer used to read from selection owners.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Wayland selection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selection {
+    Clipboard,
+    Primary,
+}
+
+/// Request sent to the worker thread, along with the future to complete with the reply.
+enum Command {
+    /// Get the MIME types offered for the selection.
+    MimeTypes(Selection, Completer<Vec<String>>),
+    /// Read the first of the given MIME types which is offered for the selection, waiting at most
+    /// the given time for each chunk of data.
+    Load(Selection, Vec<String>, Duration, Completer<(String, Vec<u8>)>),
+    /// Offer data for each of the given MIME types.
+    Store(Selection, HashMap<String, Vec<u8>>, Completer<()>),
+    /// Send changes of the selection to the given channel.
+    Watch(Selection, Sender<ClipboardEvent>, Completer<()>),
+    /// Shut down the worker.
+    Exit,
+}
+
+/// Handle to the worker thread.
+pub struct Worker {
+    requests: Sender<Command>,
+    /// Wakes the worker up when a request was sent.
+    waker: Arc<UnixStream>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    /// Start the worker thread, once the globals of `display` were bound.
+    ///
+    /// A `windowless` worker has no surfaces which could ever get focus, so it fails with
+    /// [`ClipboardError::Unsupported`] unless the compositor supports data control.
+    pub fn spawn(display: Display, windowless: bool) -> Result<Self> {
+        let (requests, receiver) = mpsc::channel();
+        let (waker, wakee) = UnixStream::pair()?;
+        wakee.set_nonblocking(true)?;
+        let waker = Arc::new(waker);
+
+        let (ready, started) = mpsc::channel();
+        let worker_waker = waker.clone();
+        let thread = thread::Builder::new().name("copypasta-wayland".into()).spawn(move || {
+            match WorkerState::new(display, windowless, receiver, worker_waker, wakee) {
+                Ok(mut state) => {
+                    let _ = ready.send(Ok(()));
+                    state.run();
+                },
+                Err(err) => {
+                    let _ = ready.send(Err(err));
+                },
+            }
+        })?;
+
+        let worker = Self { requests, waker, thread: Some(thread) };
+        started.recv().map_err(|_| ClipboardError::ConnectionLost)??;
+
+        Ok(worker)
+    }
+
+    /// Get the MIME types offered for `selection`.
+    pub fn mime_types(&self, selection: Selection) -> ClipboardFuture<Vec<String>> {
+        self.request(|reply| Command::MimeTypes(selection, reply))
+    }
+
+    /// Read the first of `mime_types` offered for `selection`.
+    ///
+    /// Fails with [`ClipboardError::Timeout`] if the owner doesn't send any data for `timeout`.
+    /// Returns the MIME type which was picked along with the data.
+    pub fn load(
+        &self,
+        selection: Selection,
+        mime_types: Vec<String>,
+        timeout: Duration,
+    ) -> ClipboardFuture<(String, Vec<u8>)> {
+        self.request(|reply| Command::Load(selection, mime_types, timeout, reply))
+    }
+
+    /// Take ownership of `selection`, offering data for every MIME type in `data`.
+    pub fn store(
+        &self,
+        selection: Selection,
+        data: HashMap<String, Vec<u8>>,
+    ) -> ClipboardFuture<()> {
+        self.request(|reply| Command::Store(selection, data, reply))
+    }
+
+    /// Send changes of `selection` to `events`, for as long as it is connected.
+    pub fn watch(
+        &self,
+        selection: Selection,
+        events: Sender<ClipboardEvent>,
+    ) -> ClipboardFuture<()> {
+        self.request(|reply| Command::Watch(selection, events, reply))
+    }
+
+    fn request<T, F>(&self, command: F) -> ClipboardFuture<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(Completer<T>) -> Command,
+    {
+        let (completer, future) = async_clipboard::channel();
+
+        // If the worker is gone, the command is dropped along with the completer, failing the
+        // future.
+        if self.requests.send(command(completer)).is_ok() {
+            let _ = (&*self.waker).write_all(&[0]);
+        }
+
+        future
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        let _ = self.requests.send(Command::Exit);
+        let _ = (&*self.waker).write_all(&[0]);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Globals used by the worker.
+pub struct ClipboardEnv {
+    seats: SeatHandler,
+    primary_selection_manager: PrimarySelectionHandler,
+    data_device_manager: DataDeviceHandler,
+    data_control_manager: SimpleGlobal<ZwlrDataControlManagerV1>,
+}
+
+impl ClipboardEnv {
+    fn new() -> Self {
+        let mut seats = SeatHandler::new();
+        let data_device_manager = DataDeviceHandler::init(&mut seats);
+        let primary_selection_manager = PrimarySelectionHandler::init(&mut seats);
+        let data_control_manager = SimpleGlobal::new();
+        Self { seats, primary_selection_manager, data_device_manager, data_control_manager }
+    }
+}
+
+impl SeatHandling for ClipboardEnv {
+    fn listen<F: FnMut(Attached<WlSeat>, &SeatData, DispatchData) + 'static>(
+        &mut self,
+        f: F,
+    ) -> SeatListener {
+        self.seats.listen(f)
+    }
+}
+
+impl PrimarySelectionHandling for ClipboardEnv {
+    fn with_primary_selection<F: FnOnce(&PrimarySelectionDevice)>(
+        &self,
+        seat: &WlSeat,
+        f: F,
+    ) -> std::result::Result<(), MissingGlobal> {
+        self.primary_selection_manager.with_primary_selection(seat, f)
+    }
+
+    fn get_primary_selection_manager(&self) -> Option<PrimarySelectionDeviceManager> {
+        self.primary_selection_manager.get_primary_selection_manager()
+    }
+}
+
+impl DataDeviceHandling for ClipboardEnv {
+    fn set_callback<F>(&mut self, callback: F) -> std::result::Result<(), MissingGlobal>
+    where
+        F: FnMut(WlSeat, DndEvent, DispatchData) + 'static,
+    {
+        self.data_device_manager.set_callback(callback)
+    }
+
+    fn with_device<F: FnOnce(&DataDevice)>(
+        &self,
+        seat: &WlSeat,
+        f: F,
+    ) -> std::result::Result<(), MissingGlobal> {
+        self.data_device_manager.with_device(seat, f)
+    }
+}
+
+sctk::environment!(ClipboardEnv,
+    singles = [
+        sctk::reexports::protocols::unstable::primary_selection::v1::client::zwp_primary_selection_device_manager_v1::ZwpPrimarySelectionDeviceManagerV1 => primary_selection_manager,
+        sctk::reexports::protocols::misc::gtk_primary_selection::client::gtk_primary_selection_device_manager::GtkPrimarySelectionDeviceManager => primary_selection_manager,
+        WlDataDeviceManager => data_device_manager,
+        ZwlrDataControlManagerV1 => data_control_manager,
+    ],
+    multis = [
+        WlSeat => seats,
+    ]
+);
+
+/// Devices of a seat.
+struct SeatDevices {
+    seat: WlSeat,
+    keyboard: Option<WlKeyboard>,
+    pointer: Option<WlPointer>,
+    selection: Option<SelectionDevices>,
+    data_control: Option<DataControlDevice>,
+}
+
+/// Dispatch data keeping track of the seats.
+///
+/// Setting a selection requires the serial of a recent input event, so the latest serial is
+/// recorded for every seat we have focus on.
+#[derive(Default)]
+pub struct Seats {
+    devices: Vec<SeatDevices>,
+    /// Seats we have focus on and their latest serial, most recently used first.
+    observed: VecDeque<(WlSeat, u32)>,
+    managers: Managers,
+    pub watchers: Watchers,
+}
+
+impl Seats {
+    fn observe(&mut self, seat: WlSeat, serial: u32) {
+        self.forget(&seat);
+        self.observed.push_front((seat, serial));
+    }
+
+    fn forget(&mut self, seat: &WlSeat) {
+        self.observed.retain(|(observed, _)| observed != seat);
+    }
+
+    /// Create or release the devices of a seat, following its capabilities.
+    fn update(&mut self, seat: &Attached<WlSeat>, data: &SeatData) {
+        let index = match self.devices.iter().position(|devices| devices.seat == **seat) {
+            Some(index) => index,
+            None => {
+                let devices = SeatDevices {
+                    seat: seat.detach(),
+                    keyboard: None,
+                    pointer: None,
+                    selection: None,
+                    data_control: None,
+                };
+                self.devices.push(devices);
+                self.devices.len() - 1
+            },
+        };
+        let devices = &mut self.devices[index];
+
+        if data.has_keyboard && !data.defunct {
+            if devices.keyboard.is_none() {
+                let keyboard = seat.get_keyboard();
+                let seat = seat.detach();
+                keyboard.quick_assign(move |_, event, dispatch_data| {
+                    keyboard_handler(&seat, event, dispatch_data)
+                });
+                devices.keyboard = Some(keyboard.detach());
+            }
+        } else if let Some(keyboard) = devices.keyboard.take() {
+            release_keyboard(keyboard);
+        }
+
+        if data.has_pointer && !data.defunct {
+            if devices.pointer.is_none() {
+                let pointer = seat.get_pointer();
+                let seat = seat.detach();
+                pointer.quick_assign(move |_, event, dispatch_data| {
+                    pointer_handler(&seat, event, dispatch_data)
+                });
+                devices.pointer = Some(pointer.detach());
+            }
+        } else if let Some(pointer) = devices.pointer.take() {
+            release_pointer(pointer);
+        }
+
+        // Data control reports every change of the selections, without the need for focus.
+        match &self.managers.data_control {
+            Some(manager) if !data.defunct => {
+                if devices.data_control.is_none() {
+                    devices.data_control = Some(DataControlDevice::new(manager, seat));
+                }
+            },
+            None if !data.defunct => {
+                if devices.selection.is_none() {
+                    devices.selection = Some(SelectionDevices::new(seat, &self.managers));
+                }
+            },
+            _ => devices.release_selections(),
+        }
+    }
+
+    /// Device used for selection requests through data control, if the compositor supports it.
+    ///
+    /// The most recently focused seat is preferred, since that's the one the user is on.
+    fn data_control(&self) -> Option<&DataControlDevice> {
+        let focused = self.observed.front().map(|(seat, _)| seat);
+        let on_focused = self.devices.iter().filter(|devices| Some(&devices.seat) == focused);
+        on_focused.chain(&self.devices).find_map(|devices| devices.data_control.as_ref())
+    }
+}
+
+impl SeatDevices {
+    fn release_selections(&mut self) {
+        if let Some(selection) = self.selection.take() {
+            selection.release();
+        }
+        if let Some(data_control) = self.data_control.take() {
+            data_control.release();
+        }
+    }
+}
+
+fn keyboard_handler(seat: &WlSeat, event: KeyboardEvent, mut dispatch_data: DispatchData) {
+    let seats = match dispatch_data.get::<Seats>() {
+        Some(seats) => seats,
+        None => return,
+    };
+
+    match event {
+        KeyboardEvent::Enter { serial, .. } | KeyboardEvent::Key { serial, .. } => {
+            seats.observe(seat.clone(), serial)
+        },
+        KeyboardEvent::Leave { .. } => seats.forget(seat),
+        KeyboardEvent::Keymap { fd, .. } => {
+            // We don't need the keymap, but the file descriptor must not leak.
+            drop(unsafe { File::from_raw_fd(fd) });
+        },
+        _ => (),
+    }
+}
+
+fn pointer_handler(seat: &WlSeat, event: PointerEvent, mut dispatch_data: DispatchData) {
+    let seats = match dispatch_data.get::<Seats>() {
+        Some(seats) => seats,
+        None => return,
+    };
+
+    match event {
+        PointerEvent::Enter { serial, .. } | PointerEvent::Button { serial, .. } => {
+            seats.observe(seat.clone(), serial)
+        },
+        _ => (),
+    }
+}
+
+fn release_keyboard(keyboard: WlKeyboard) {
+    if keyboard.as_ref().version() >= 3 {
+        keyboard.release();
+    }
+}
+
+fn release_pointer(pointer: WlPointer) {
+    if pointer.as_ref().version() >= 3 {
+        pointer.release();
+    }
+}
+
+/// State owned by the worker thread.
+struct WorkerState {
+    env: Environment<ClipboardEnv>,
+    queue: EventQueue,
+    seats: Seats,
+    _seat_listener: SeatListener,
+    requests: Receiver<Command>,
+    /// Sending end of `wakee`, used to notice cancelled reads right away.
+    waker: Arc<UnixStream>,
+    wakee: UnixStream,
+    reads: Vec<PendingRead>,
+}
+
+/// Data being received from a selection owner.
+struct PendingRead {
+    pipe: File,
+    mime_type: String,
+    data: Vec<u8>,
+    /// Time to wait for each chunk of data.
+    timeout: Duration,
+    deadline: Instant,
+    completer: Completer<(String, Vec<u8>)>,
+}
+
+impl PendingRead {
+    /// Read what the owner sent so far.
+    ///
+    /// Returns `None` while the owner may still send more data.
+    fn read_available(&mut self) -> Option<Result<Vec<u8>>> {
+        let mut chunk = [0; READ_CHUNK_SIZE];
+        match self.pipe.read(&mut chunk) {
+            Ok(0) => return Some(Ok(mem::take(&mut self.data))),
+            Ok(len) => {
+                self.data.extend_from_slice(&chunk[..len]);
+                self.deadline = Instant::now() + self.timeout;
+            },
+            Err(err)
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::Interrupted => {},
+            Err(err) => return Some(Err(err.into())),
+        }
+
+        // An owner which never closes the pipe must not keep the read going forever.
+        if Instant::now() >= self.deadline {
+            Some(Err(ClipboardError::Timeout))
+        } else {
+            None
+        }
+    }
+}
+
+impl WorkerState {
+    fn new(
+        display: Display,
+        windowless: bool,
+        requests: Receiver<Command>,
+        waker: Arc<UnixStream>,
+        wakee: UnixStream,
+    ) -> Result<Self> {
+        let mut queue = display.create_event_queue();
+        let display = display.attach(queue.token());
+        let env = Environment::new(&display, &mut queue, ClipboardEnv::new())?;
+
+        let managers = Managers {
+            data_device: env.get_global::<WlDataDeviceManager>(),
+            primary_selection: env.get_primary_selection_manager(),
+            data_control: env.get_global::<ZwlrDataControlManagerV1>(),
+        };
+
+        let usable = match managers.data_control {
+            Some(_) => true,
+            None => !windowless && managers.data_device.is_some(),
+        };
+        if !usable {
+            return Err(ClipboardError::Unsupported);
+        }
+
+        let mut seats = Seats { managers, ..Seats::default() };
+        for seat in env.get_all_seats() {
+            if let Some(data) = seat::clone_seat_data(&seat) {
+                seats.update(&seat, &data);
+            }
+        }
+
+        let seat_listener = env.listen_for_seats(|seat, data, mut dispatch_data| {
+            if let Some(seats) = dispatch_data.get::<Seats>() {
+                seats.update(&seat, data);
+            }
+        });
+
+        Ok(Self {
+            env,
+            queue,
+            seats,
+            _seat_listener: seat_listener,
+            requests,
+            waker,
+            wakee,
+            reads: Vec::new(),
+        })
+    }
+
+    fn run(&mut self) {
+        loop {
+            // Drain the wakeup notifications, since every request is handled below anyway.
+            let _ = (&self.wakee).read(&mut [0; 64]);
+
+            loop {
+                match self.requests.try_recv() {
+                    Ok(Command::Exit) | Err(TryRecvError::Disconnected) => return self.shutdown(),
+                    Ok(command) => self.handle_command(command),
+                    Err(TryRecvError::Empty) => break,
+                }
+            }
+
+            if self.dispatch().is_err() {
+                return self.shutdown();
+            }
+
+            self.read_pipes();
+
+            self.wait();
+        }
+    }
+
+    /// Block until either the display, a request or a read needs attention.
+    fn wait(&self) {
+        let _ = self.queue.display().flush();
+
+        let mut fds = vec![
+            libc::pollfd {
+                fd: self.queue.display().get_connection_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            },
+            libc::pollfd { fd: self.wakee.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+        ];
+        for read in &self.reads {
+            fds.push(libc::pollfd { fd: read.pipe.as_raw_fd(), events: libc::POLLIN, revents: 0 });
+        }
+
+        // Round up, to avoid spinning once less than a millisecond is left.
+        let timeout = match self.reads.iter().map(|read| read.deadline).min() {
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                (remaining.as_millis() + 1).min(i32::MAX as u128) as i32
+            },
+            None => -1,
+        };
+
+        unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) };
+    }
+
+    /// Receive the data sent by selection owners, completing the reads which are over.
+    fn read_pipes(&mut self) {
+        for mut read in mem::take(&mut self.reads) {
+            // Dropping the read closes the pipe, letting the owner know we're not interested.
+            if read.completer.is_cancelled() {
+                continue;
+            }
+
+            match read.read_available() {
+                Some(result) => {
+                    let mime_type = read.mime_type;
+                    read.completer.complete(result.map(|data| (mime_type, data)));
+                },
+                None => self.reads.push(read),
+            }
+        }
+    }
+
+    /// Read and dispatch the events available on the display.
+    fn dispatch(&mut self) -> io::Result<u32> {
+        if let Some(guard) = self.queue.prepare_read() {
+            match guard.read_events() {
+                Err(err) if err.kind() != io::ErrorKind::WouldBlock => return Err(err),
+                _ => (),
+            }
+        }
+
+        self.queue.dispatch_pending(&mut self.seats, |_, _, _| {})
+    }
+
+    /// Make sure the selection state is up to date.
+    fn roundtrip(&mut self) -> Result<()> {
+        self.queue
+            .sync_roundtrip(&mut self.seats, |_, _, _| {})
+            .map(drop)
+            .map_err(|_| ClipboardError::ConnectionLost)
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::MimeTypes(selection, completer) => {
+                completer.complete(self.mime_types(selection));
+            },
+            Command::Load(_, _, _, completer) if completer.is_cancelled() => (),
+            Command::Load(selection, mime_types, timeout, completer) => {
+                let pipe = self.load(selection, &mime_types).and_then(|(mime_type, pipe)| {
+                    set_nonblocking(&pipe)?;
+                    Ok((mime_type, pipe))
+                });
+
+                // The data is read along with the other events, so we can keep serving our own
+                // selection in the meantime.
+                match pipe {
+                    Ok((mime_type, pipe)) => {
+                        let waker = self.waker.clone();
+                        completer.on_cancel(move || {
+                            let _ = (&*waker).write_all(&[0]);
+                        });
+
+                        let deadline = Instant::now() + timeout;
+                        let data = Vec::new();
+                        self.reads.push(PendingRead {
+                            pipe,
+                            mime_type,
+                            data,
+                            timeout,
+                            deadline,
+                            completer,
+                        });
+                    },
+                    Err(err) => completer.complete(Err(err)),
+                }
+            },
+            Command::Store(selection, data, completer) => {
+                completer.complete(self.store(selection, data));
+            },
+            Command::Watch(selection, events, completer) => {
+                completer.complete(self.watch(selection, events));
+            },
+            Command::Exit => unreachable!(),
+        }
+    }
+
+    /// Seat which should be used for selection requests.
+    fn seat(&self) -> Result<(WlSeat, u32)> {
+        self.seats.observed.front().cloned().ok_or(ClipboardError::NoFocus)
+    }
+
+    fn mime_types(&mut self, selection: Selection) -> Result<Vec<String>> {
+        self.roundtrip()?;
+
+        if let Some(device) = self.seats.data_control() {
+            return Ok(device.mime_types(selection).unwrap_or_default());
+        }
+
+        let (seat, _) = self.seat()?;
+
+        let mut mime_types = Vec::new();
+        let result = match selection {
+            Selection::Clipboard => self.env.with_data_device(&seat, |device| {
+                device.with_selection(|offer| {
+                    if let Some(offer) = offer {
+                        mime_types = offer.with_mime_types(<[String]>::to_vec);
+                    }
+                })
+            }),
+            Selection::Primary => self.env.with_primary_selection(&seat, |device| {
+                device.with_selection(|offer| {
+                    if let Some(offer) = offer {
+                        mime_types = offer.with_mime_types(<[String]>::to_vec);
+                    }
+                })
+            }),
+        };
+        result.map_err(|_| ClipboardError::Unsupported)?;
+
+        Ok(mime_types)
+    }
+
+    fn load(&mut self, selection: Selection, mime_types: &[String]) -> Result<(String, File)> {
+        self.roundtrip()?;
+
+        if let Some(device) = self.seats.data_control() {
+            let pipe = device.receive(selection, mime_types)?;
+            self.queue.display().flush().map_err(|_| ClipboardError::ConnectionLost)?;
+            return Ok(pipe);
+        }
+
+        let (seat, _) = self.seat()?;
+
+        let pick =
+            |offered: &[String]| mime_types.iter().find(|mime| offered.contains(mime)).cloned();
+
+        let mut pipe = None;
+        let result = match selection {
+            Selection::Clipboard => self.env.with_data_device(&seat, |device| {
+                device.with_selection(|offer| {
+                    pipe = offer.map(|offer| match offer.with_mime_types(pick) {
+                        Some(mime_type) => offer
+                            .receive(mime_type.clone())
+                            .map(|pipe| (mime_type, pipe))
+                            .map_err(ClipboardError::from),
+                        None => Err(ClipboardError::FormatNotAvailable),
+                    });
+                })
+            }),
+            Selection::Primary => self.env.with_primary_selection(&seat, |device| {
+                device.with_selection(|offer| {
+                    pipe = offer.map(|offer| match offer.with_mime_types(pick) {
+                        Some(mime_type) => offer
+                            .receive(mime_type.clone())
+                            .map(|pipe| (mime_type, pipe))
+                            .map_err(ClipboardError::from),
+                        None => Err(ClipboardError::FormatNotAvailable),
+                    });
+                })
+            }),
+        };
+        result.map_err(|_| ClipboardError::Unsupported)?;
+
+        let (mime_type, pipe) = pipe.unwrap_or(Err(ClipboardError::Empty))?;
+
+        // The request has to reach the owner before anything can be read.
+        self.queue.display().flush().map_err(|_| ClipboardError::ConnectionLost)?;
+
+        Ok((mime_type, unsafe { File::from_raw_fd(pipe.into_raw_fd()) }))
+    }
+
+    fn store(&mut self, selection: Selection, data: HashMap<String, Vec<u8>>) -> Result<()> {
+        self.roundtrip()?;
+
+        if let (Some(manager), Some(device)) =
+            (&self.seats.managers.data_control, self.seats.data_control())
+        {
+            device.set_selection(manager, selection, data)?;
+            return self.queue.display().flush().map_err(|_| ClipboardError::ConnectionLost);
+        }
+
+        let (seat, serial) = self.seat()?;
+
+        let mime_types = data.keys().cloned().collect();
+        let data = Arc::new(data);
+
+        let result = match selection {
+            Selection::Clipboard => {
+                if self.env.get_global::<WlDataDeviceManager>().is_none() {
+                    return Err(ClipboardError::Unsupported);
+                }
+
+                let source = self.env.new_data_source(mime_types, move |event, _| {
+                    if let DataSourceEvent::Send { mime_type, pipe } = event {
+                        send_data(&data, mime_type, pipe);
+                    }
+                });
+                self.env
+                    .with_data_device(&seat, |device| device.set_selection(&Some(source), serial))
+            },
+            Selection::Primary => {
+                if self.env.get_primary_selection_manager().is_none() {
+                    return Err(ClipboardError::Unsupported);
+                }
+
+                let source = self.env.new_primary_selection_source(mime_types, move |event, _| {
+                    if let PrimarySelectionSourceEvent::Send { mime_type, pipe } = event {
+                        send_data(&data, mime_type, pipe);
+                    }
+                });
+                self.env.with_primary_selection(&seat, |device| {
+                    device.set_selection(&Some(source), serial)
+                })
+            },
+        };
+        result.map_err(|_| ClipboardError::Unsupported)?;
+
+        self.queue.display().flush().map_err(|_| ClipboardError::ConnectionLost)
+    }
+
+    fn watch(&mut self, selection: Selection, events: Sender<ClipboardEvent>) -> Result<()> {
+        let managers = &self.seats.managers;
+        let supported = match selection {
+            _ if managers.data_control.is_some() => true,
+            Selection::Clipboard => managers.data_device.is_some(),
+            Selection::Primary => managers.primary_selection.is_some(),
+        };
+        if !supported {
+            return Err(ClipboardError::Unsupported);
+        }
+
+        self.seats.watchers.add(selection, events);
+        Ok(())
+    }
+
+    fn shutdown(&mut self) {
+        // Proxies have to be released from this thread, before the queue goes away.
+        for mut devices in self.seats.devices.drain(..) {
+            if let Some(keyboard) = devices.keyboard.take() {
+                release_keyboard(keyboard);
+            }
+            if let Some(pointer) = devices.pointer.take() {
+                release_pointer(pointer);
+            }
+            devices.release_selections();
+        }
+
+        let _ = self.roundtrip();
+        let _ = self.queue.display().flush();
+    }
+}
+
+fn set_nonblocking(file: &File) -> Result<()> {
+    let fd = file.as_raw_fd();
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags == -1 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } == -1 {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+/// Write the data for `mime_type` to a client pasting from us.
+///
+/// This happens on a separate thread, so a slow reader doesn't block the worker.
+pub fn send_data<W>(data: &Arc<HashMap<String, Vec<u8>>>, mime_type: String, mut pipe: W)
+where
+    W: Write + Send + 'static,
+{
+    let data = data.clone();
+    thread::spawn(move || {
+        if let Some(data) = data.get(&mime_type) {
+            // The reader closing the pipe early is not our concern.
+            let _ = pipe.write_all(data);
+        }
+    });
+}
@@ -14,7 +14,7 @@
 
 use clipboard_win::{get_clipboard_string, set_clipboard_string};
 
-use crate::common::{ClipboardProvider, Result};
+use crate::common::{ClipboardProvider, ContentType, Namespace, Result};
 
 pub struct WindowsClipboardContext;
 
@@ -25,11 +25,23 @@ impl WindowsClipboardContext {
 }
 
 impl ClipboardProvider for WindowsClipboardContext {
-    fn get_contents(&mut self) -> Result<String> {
+    fn get_contents(&self) -> Result<String> {
         Ok(get_clipboard_string()?)
     }
 
-    fn set_contents(&mut self, data: String) -> Result<()> {
+    fn set_contents(&self, data: String) -> Result<()> {
+        self.set_text(&data)
+    }
+
+    fn set_text(&self, data: &str) -> Result<()> {
         Ok(set_clipboard_string(&data)?)
     }
+
+    fn normalize_content_type(ct: ContentType) -> ContentType {
+        ct.normalize(Namespace::Windows)
+    }
+
+    fn denormalize_content_type(ct: ContentType) -> String {
+        ct.name(Namespace::Windows)
+    }
 }
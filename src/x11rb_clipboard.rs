@@ -1,56 +1,480 @@
-use crate::common::{ClipboardProvider, Result};
-use crate::ContentType;
-use std::convert::TryInto;
-use std::time::{SystemTime, UNIX_EPOCH};
-use x11rb::connection::Connection;
+use crate::common::{
+    ClipboardError, ClipboardEvent, ClipboardProvider, ClipboardWatch, ContentType, Namespace,
+    Result,
+};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use x11rb::connection::{Connection, RequestConnection as _};
+use x11rb::errors::{ConnectError, ConnectionError, ReplyError, ReplyOrIdError};
+use x11rb::protocol::xfixes::{
+    self, ConnectionExt as _, SelectionEvent, SelectionEventMask,
+    SelectionNotifyEvent as XfixesSelectionNotifyEvent,
+};
+#[cfg(feature = "image-data")]
+use x11rb::protocol::xproto::ImageFormat;
 use x11rb::protocol::xproto::{
-    Atom, ConnectionExt, CreateWindowAux, EventMask, GetPropertyReply, Gravity, Timestamp, Window,
-    WindowClass,
+    Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, CreateWindowAux, EventMask,
+    GetPropertyReply, PropMode, Property, SelectionNotifyEvent, SelectionRequestEvent, Timestamp,
+    Window, WindowClass, SELECTION_NOTIFY_EVENT,
 };
 use x11rb::protocol::Event;
 use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{CURRENT_TIME, NONE};
 
-pub struct X11RbClipboardContext {
-    connection: RustConnection,
-    window: Window,
+use self::router::{EventRouter, Requestor};
+use crate::async_clipboard::{block_on, AsyncClipboardProvider, ClipboardFuture};
+#[cfg(feature = "image-data")]
+use crate::image_data::{self, ImageData};
+
+mod compound_text;
+#[cfg(feature = "image-data")]
+mod pixmap;
+mod router;
+mod timer;
+
+/// Number of 32-bit units requested at once when reading a property.
+const PROPERTY_CHUNK_LENGTH: u32 = 1024 * 1024;
+
+/// Time to wait for the selection owner, unless configured otherwise.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Size of the chunks sent during an INCR transfer.
+const INCR_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Targets which describe the selection, rather than provide a representation of its content.
+const META_TARGETS: [&str; 5] = ["TARGETS", "TIMESTAMP", "MULTIPLE", "SAVE_TARGETS", "DELETE"];
+
+/// X11 selection which can be used as a clipboard.
+pub trait Selection: Send + Sync {
+    /// Name of the selection's atom.
+    fn name() -> &'static str;
+}
+
+/// The `PRIMARY` selection, usually set by selecting text and pasted with the middle button.
+pub struct Primary;
+
+impl Selection for Primary {
+    fn name() -> &'static str {
+        "PRIMARY"
+    }
+}
+
+/// The `SECONDARY` selection, which is rarely used by modern applications.
+pub struct Secondary;
+
+impl Selection for Secondary {
+    fn name() -> &'static str {
+        "SECONDARY"
+    }
+}
+
+/// The `CLIPBOARD` selection, used by explicit copy and paste actions.
+pub struct Clipboard;
 
-    clipboard: Atom,
+impl Selection for Clipboard {
+    fn name() -> &'static str {
+        "CLIPBOARD"
+    }
+}
+
+/// Clipboard accessed through an X11 selection.
+///
+/// Dropping a context which owns `CLIPBOARD` hands its contents over to the clipboard manager,
+/// like [`persist`](Self::persist) does. This blocks until the manager saved them, for up to the
+/// [timeout](Self::set_timeout), even when dropped on an asynchronous runtime. Errors can't be
+/// reported from `Drop`, so applications which want to know whether the contents survive should
+/// call `persist` themselves, and can turn the handover on drop off with
+/// [`set_persist_on_drop`](Self::set_persist_on_drop).
+pub struct X11RbClipboardContext<S = Clipboard>
+where
+    S: Selection,
+{
+    requester: Arc<Requester>,
+    persist_on_drop: AtomicBool,
+    _selection: PhantomData<S>,
+}
+
+/// Requesting side of the selection, shared with the asynchronous operations in progress.
+struct Requester {
+    connection: Arc<RustConnection>,
+    selection: Atom,
+    atoms: Atoms,
+    owner: SelectionOwner,
+    events: EventRouter,
+    timeout: Mutex<Duration>,
+}
+
+/// Atoms used by both the requesting and the owning side of the selection.
+///
+/// Atoms are server-global, so the values interned on one connection are valid on the other.
+#[derive(Clone, Copy, Debug)]
+struct Atoms {
     utf8_string: Atom,
+    string: Atom,
+    text: Atom,
+    compound_text: Atom,
+    text_plain_utf8: Atom,
     targets: Atom,
     property: Atom,
     atom: Atom,
+    integer: Atom,
+    incr: Atom,
+    timestamp: Atom,
+    multiple: Atom,
+    atom_pair: Atom,
+    clipboard_manager: Atom,
+    save_targets: Atom,
+    /// Property used to obtain server timestamps.
+    timestamp_property: Atom,
+}
+
+impl Atoms {
+    fn new(connection: &RustConnection) -> Result<Self> {
+        Ok(Self {
+            utf8_string: intern_atom(connection, "UTF8_STRING")?,
+            string: AtomEnum::STRING.into(),
+            text: intern_atom(connection, "TEXT")?,
+            compound_text: intern_atom(connection, "COMPOUND_TEXT")?,
+            text_plain_utf8: intern_atom(connection, "text/plain;charset=utf-8")?,
+            targets: intern_atom(connection, "TARGETS")?,
+            property: intern_atom(connection, "PROPERTY")?,
+            atom: AtomEnum::ATOM.into(),
+            integer: AtomEnum::INTEGER.into(),
+            incr: intern_atom(connection, "INCR")?,
+            timestamp: intern_atom(connection, "TIMESTAMP")?,
+            multiple: intern_atom(connection, "MULTIPLE")?,
+            atom_pair: intern_atom(connection, "ATOM_PAIR")?,
+            clipboard_manager: intern_atom(connection, "CLIPBOARD_MANAGER")?,
+            save_targets: intern_atom(connection, "SAVE_TARGETS")?,
+            timestamp_property: intern_atom(connection, "COPYPASTA_TIMESTAMP")?,
+        })
+    }
 }
 
-impl X11RbClipboardContext {
+impl From<ConnectError> for ClipboardError {
+    fn from(err: ConnectError) -> Self {
+        ClipboardError::Backend(err.into())
+    }
+}
+
+impl From<ConnectionError> for ClipboardError {
+    fn from(err: ConnectionError) -> Self {
+        match err {
+            ConnectionError::IOError(_) => ClipboardError::ConnectionLost,
+            err => ClipboardError::Backend(err.into()),
+        }
+    }
+}
+
+impl From<ReplyError> for ClipboardError {
+    fn from(err: ReplyError) -> Self {
+        match err {
+            ReplyError::ConnectionError(err) => err.into(),
+            err => ClipboardError::Backend(err.into()),
+        }
+    }
+}
+
+impl From<ReplyOrIdError> for ClipboardError {
+    fn from(err: ReplyOrIdError) -> Self {
+        match err {
+            ReplyOrIdError::ConnectionError(err) => err.into(),
+            err => ClipboardError::Backend(err.into()),
+        }
+    }
+}
+
+/// Data served while we own the selection.
+struct Contents {
+    /// Type and data of the property sent for each target.
+    targets: HashMap<Atom, (Atom, Vec<u8>)>,
+    /// Server time at which we acquired the selection.
+    acquired: Timestamp,
+}
+
+impl Contents {
+    /// Check whether the contents were ours at `time`.
+    ///
+    /// Requests made before we acquired the selection are refused, as required by the ICCCM.
+    fn owned_at(&self, time: Timestamp) -> bool {
+        time == CURRENT_TIME || !time_before(time, self.acquired)
+    }
+}
+
+/// Owning side of the selection.
+///
+/// X11 has no clipboard storage: the owner of a selection has to answer every conversion request
+/// for as long as it wants the data to be available. This is done from a background thread with
+/// its own connection, so that requests are served while the user thread is doing other things
+/// (including pasting from itself).
+struct SelectionOwner {
+    connection: Arc<RustConnection>,
+    window: Window,
+    contents: Arc<Mutex<Option<Contents>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SelectionOwner {
+    fn new(atoms: Atoms) -> Result<Self> {
+        let (connection, screen_num) = RustConnection::connect(None)?;
+        let window = create_window(&connection, screen_num, EventMask::STRUCTURE_NOTIFY)?;
+
+        let connection = Arc::new(connection);
+        let contents = Arc::new(Mutex::new(None));
+        let thread = {
+            let connection = connection.clone();
+            let contents = contents.clone();
+            thread::Builder::new()
+                .name("copypasta-x11rb".into())
+                .spawn(move || SelectionServer::new(&connection, window, atoms, &contents).run())?
+        };
+
+        Ok(Self { connection, window, contents, thread: Some(thread) })
+    }
+
+    /// Take ownership of `selection`, using the server timestamp `time`.
+    fn set_contents(
+        &self,
+        selection: Atom,
+        targets: HashMap<Atom, (Atom, Vec<u8>)>,
+        time: Timestamp,
+    ) -> Result<()> {
+        *self.contents.lock().unwrap() = Some(Contents { targets, acquired: time });
+
+        self.connection.set_selection_owner(self.window, selection, time)?;
+        let owner = self.connection.get_selection_owner(selection)?.reply()?.owner;
+        if owner == self.window {
+            Ok(())
+        } else {
+            self.contents.lock().unwrap().take();
+            Err(ClipboardError::OwnershipRefused)
+        }
+    }
+}
+
+impl Drop for SelectionOwner {
+    fn drop(&mut self) {
+        // Destroying the window gives up the selection and makes the serving thread exit.
+        let _ = self.connection.destroy_window(self.window);
+        let _ = self.connection.flush();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Background thread reporting changes of a selection's owner through XFixes.
+struct SelectionWatcher {
+    connection: Arc<RustConnection>,
+    window: Window,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SelectionWatcher {
+    fn new(selection: Atom, events: Sender<ClipboardEvent>) -> Result<Self> {
+        let (connection, screen_num) = RustConnection::connect(None)?;
+        let window = create_window(&connection, screen_num, EventMask::STRUCTURE_NOTIFY)?;
+
+        if connection.extension_information(xfixes::X11_EXTENSION_NAME)?.is_none() {
+            return Err(ClipboardError::Unsupported);
+        }
+
+        // The extension version has to be negotiated before using any of its requests.
+        connection.xfixes_query_version(5, 0)?.reply()?;
+
+        let mask = SelectionEventMask::SET_SELECTION_OWNER
+            | SelectionEventMask::SELECTION_WINDOW_DESTROY
+            | SelectionEventMask::SELECTION_CLIENT_CLOSE;
+        connection.xfixes_select_selection_input(window, selection, mask)?.check()?;
+
+        let connection = Arc::new(connection);
+        let thread = {
+            let connection = connection.clone();
+            thread::Builder::new()
+                .name("copypasta-x11rb-watch".into())
+                .spawn(move || watch_selection(&connection, window, &events))?
+        };
+
+        Ok(Self { connection, window, thread: Some(thread) })
+    }
+}
+
+impl Drop for SelectionWatcher {
+    fn drop(&mut self) {
+        // Destroying the window makes the watching thread exit.
+        let _ = self.connection.destroy_window(self.window);
+        let _ = self.connection.flush();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Forward XFixes selection events to `events`, until `window` is destroyed.
+fn watch_selection(connection: &RustConnection, window: Window, events: &Sender<ClipboardEvent>) {
+    while let Ok(event) = connection.wait_for_event() {
+        let event = match event {
+            Event::XfixesSelectionNotify(ev) => selection_event(&ev),
+            Event::DestroyNotify(ev) if ev.window == window => return,
+            _ => continue,
+        };
+
+        // The watch was dropped, but the window is not destroyed yet.
+        if events.send(event).is_err() {
+            return;
+        }
+    }
+}
+
+fn selection_event(ev: &XfixesSelectionNotifyEvent) -> ClipboardEvent {
+    if ev.subtype == SelectionEvent::SET_SELECTION_OWNER && ev.owner != NONE {
+        ClipboardEvent::Changed
+    } else {
+        ClipboardEvent::Cleared
+    }
+}
+
+impl<S> X11RbClipboardContext<S>
+where
+    S: Selection,
+{
     pub fn new() -> Result<Self> {
         let (connection, screen_num) = RustConnection::connect(None)?;
-        let screen = &connection.setup().roots[screen_num];
-        let window = connection.generate_id()?;
-
-        let win_aux = CreateWindowAux::new();
-        let cookie = connection.create_window(
-            screen.root_depth,
-            window,
-            screen.root,
-            0,
-            0,
-            1,
-            1,
-            0,
-            WindowClass::INPUT_OUTPUT,
-            0,
-            &win_aux,
+        let selection = intern_atom(&connection, S::name())?;
+        let atoms = Atoms::new(&connection)?;
+        let owner = SelectionOwner::new(atoms)?;
+
+        let connection = Arc::new(connection);
+        let events = EventRouter::new(connection.clone(), screen_num)?;
+
+        let requester = Requester {
+            connection,
+            selection,
+            atoms,
+            owner,
+            events,
+            timeout: Mutex::new(DEFAULT_TIMEOUT),
+        };
+        Ok(Self {
+            requester: Arc::new(requester),
+            persist_on_drop: AtomicBool::new(true),
+            _selection: PhantomData,
+        })
+    }
+
+    /// Set how long to wait for the selection owner to answer a request.
+    ///
+    /// This also bounds how long [`persist`](Self::persist) waits for the clipboard manager.
+    ///
+    /// Defaults to 3 seconds.
+    pub fn set_timeout(&self, timeout: Duration) {
+        *self.requester.timeout.lock().unwrap() = timeout;
+    }
+
+    /// Hand the contents we copied over to the clipboard manager, so they outlive this context.
+    ///
+    /// X11 clipboards lose their contents when the owner exits, unless a clipboard manager saved
+    /// them through the ICCCM `SAVE_TARGETS` protocol. This is done automatically when the context
+    /// is dropped, unless [disabled](Self::set_persist_on_drop), but errors are then ignored.
+    ///
+    /// Nothing is done if another client owns the selection. Fails with
+    /// [`ClipboardError::Unsupported`] if no clipboard manager is running, or for selections other
+    /// than `CLIPBOARD`, which clipboard managers don't save.
+    pub fn persist(&self) -> Result<()> {
+        if S::name() != Clipboard::name() {
+            return Err(ClipboardError::Unsupported);
+        }
+
+        block_on(self.requester.persist())
+    }
+
+    /// Set whether dropping the context hands its contents over to the clipboard manager.
+    ///
+    /// Enabled by default.
+    pub fn set_persist_on_drop(&self, persist: bool) {
+        self.persist_on_drop.store(persist, Ordering::Relaxed);
+    }
+}
+
+impl Requester {
+    fn timeout(&self) -> Duration {
+        *self.timeout.lock().unwrap()
+    }
+
+    /// Hand our contents over to the clipboard manager, see [`X11RbClipboardContext::persist`].
+    async fn persist(&self) -> Result<()> {
+        // Meta targets are answered by the owner itself, only the data needs to be saved.
+        let targets: Vec<Atom> = match self.owner.contents.lock().unwrap().as_ref() {
+            Some(contents) => contents.targets.keys().copied().collect(),
+            None => return Ok(()),
+        };
+
+        let owner = self.connection.get_selection_owner(self.selection)?.reply()?.owner;
+        if owner != self.owner.window {
+            return Ok(());
+        }
+
+        let manager = self.atoms.clipboard_manager;
+        if self.connection.get_selection_owner(manager)?.reply()?.owner == NONE {
+            return Err(ClipboardError::Unsupported);
+        }
+
+        self.save_targets(manager, &targets).await
+    }
+
+    /// Ask the clipboard `manager` to save `targets`, and wait for it to be done.
+    async fn save_targets(&self, manager: Atom, targets: &[Atom]) -> Result<()> {
+        let requestor = self.events.requestor()?;
+        self.connection.change_property32(
+            PropMode::REPLACE,
+            requestor.window,
+            self.atoms.property,
+            self.atoms.atom,
+            targets,
+        )?;
+        let time = self.server_time(&requestor).await?;
+        let cookie = self.connection.convert_selection(
+            requestor.window,
+            manager,
+            self.atoms.save_targets,
+            self.atoms.property,
+            time,
         )?;
         cookie.check()?;
+        self.connection.flush()?;
 
-        let clipboard = intern_atom(&connection, "CLIPBOARD")?;
-        let utf8_string = intern_atom(&connection, "UTF8_STRING")?;
-        let targets = intern_atom(&connection, "TARGETS")?;
-        let property = intern_atom(&connection, "PROPERTY")?;
-        let atom = intern_atom(&connection, "ATOM")?;
-        Ok(Self { connection, window, clipboard, utf8_string, targets, property, atom })
+        // The manager answers once it converted all of the targets, which our owner thread serves
+        // in the meantime.
+        let deadline = Instant::now() + self.timeout();
+        let ev = loop {
+            match requestor.next_event(deadline).await? {
+                Event::SelectionNotify(ev)
+                    if ev.selection == manager && ev.target == self.atoms.save_targets =>
+                {
+                    break ev
+                },
+                _ => (),
+            }
+        };
+
+        if ev.property == NONE {
+            return Err("clipboard manager failed to save the clipboard".into());
+        }
+
+        Ok(())
     }
 
+    /// Read a property in its entirety, regardless of its size.
+    ///
+    /// If `delete` is set, the property is deleted by the server once its last chunk was read.
     fn get_full_property<A, B>(
         &self,
         delete: bool,
@@ -62,91 +486,702 @@ impl X11RbClipboardContext {
         A: Into<Atom>,
         B: Into<Atom>,
     {
-        let cookie = self.connection.get_property(delete, window, property, type_, 0, u32::MAX)?;
-        let reply = cookie.reply()?;
-        debug_assert_eq!(reply.bytes_after, 0);
+        let (property, type_) = (property.into(), type_.into());
+
+        let get_chunk = |offset| {
+            self.connection.get_property(
+                delete,
+                window,
+                property,
+                type_,
+                offset,
+                PROPERTY_CHUNK_LENGTH,
+            )
+        };
+
+        let mut reply = get_chunk(0)?.reply()?;
+        while reply.bytes_after > 0 {
+            // Offsets are expressed in 32-bit units, and all chunks but the last one are a
+            // multiple of that.
+            let offset = (reply.value.len() / 4) as u32;
+            let chunk = get_chunk(offset)?.reply()?;
+            reply.value.extend_from_slice(&chunk.value);
+            reply.value_len += chunk.value_len;
+            reply.bytes_after = chunk.bytes_after;
+        }
+
         Ok(reply)
     }
-}
 
-impl ClipboardProvider for X11RbClipboardContext {
-    fn get_contents(&self) -> Result<String> {
+    /// Convert the selection to `target`, and return the resulting property.
+    ///
+    /// Large values sent through the INCR mechanism are transparently reassembled.
+    async fn convert_selection(&self, target: Atom) -> Result<GetPropertyReply> {
+        let selection = self.selection;
+
+        let owner = self.connection.get_selection_owner(selection)?.reply()?.owner;
+        if owner == NONE {
+            return Err(ClipboardError::Empty);
+        }
+
+        let requestor = self.events.requestor()?;
+        let time = self.server_time(&requestor).await?;
         let cookie = self.connection.convert_selection(
-            self.window,
-            self.clipboard,
-            self.utf8_string,
-            self.property,
-            current_time(),
+            requestor.window,
+            selection,
+            target,
+            self.atoms.property,
+            time,
         )?;
         cookie.check()?;
         self.connection.flush()?;
 
+        let deadline = Instant::now() + self.timeout();
         loop {
-            let event = self.connection.wait_for_event()?;
-            match event {
-                Event::SelectionNotify(_ev) => {
-                    let val = self.get_full_property(
-                        false,
-                        self.window,
-                        self.property,
-                        self.utf8_string,
-                    )?;
-                    return String::from_utf8(val.value).map_err(|e| Box::new(e) as _);
+            // Other events are property changes caused by the transfer itself. Some owners answer
+            // with `CurrentTime` instead of the time of the request, which ICCCM tolerates.
+            let ev = match requestor.next_event(deadline).await? {
+                Event::SelectionNotify(ev)
+                    if ev.selection == selection
+                        && ev.target == target
+                        && (ev.time == time || ev.time == CURRENT_TIME) =>
+                {
+                    ev
                 },
-                _ => {
-                    dbg!("Have event {:?}", event);
+                _ => continue,
+            };
+
+            // The owner could not convert the selection to the requested target.
+            if ev.property == NONE {
+                return Err(ClipboardError::FormatNotAvailable);
+            } else if ev.property != self.atoms.property {
+                continue;
+            }
+
+            let reply =
+                self.get_full_property(true, requestor.window, self.atoms.property, AtomEnum::ANY)?;
+            self.connection.flush()?;
+
+            return if reply.type_ == self.atoms.incr {
+                self.receive_incr(&requestor).await
+            } else {
+                Ok(reply)
+            };
+        }
+    }
+
+    /// Receive the chunks of an INCR transfer.
+    ///
+    /// The transfer was initiated by deleting the INCR property. The owner then writes every
+    /// chunk to the property, waiting for us to delete it before sending the next one. A zero
+    /// length chunk marks the end of the transfer.
+    ///
+    /// The timeout applies to each chunk, rather than to the whole transfer.
+    async fn receive_incr(&self, requestor: &Requestor<'_>) -> Result<GetPropertyReply> {
+        let mut transfer: Option<GetPropertyReply> = None;
+        let mut deadline = Instant::now() + self.timeout();
+        loop {
+            match requestor.next_event(deadline).await? {
+                Event::PropertyNotify(ev)
+                    if ev.atom == self.atoms.property && ev.state == Property::NEW_VALUE =>
+                {
+                    let chunk = self.get_full_property(
+                        true,
+                        requestor.window,
+                        self.atoms.property,
+                        AtomEnum::ANY,
+                    )?;
+                    self.connection.flush()?;
+
+                    if chunk.value.is_empty() {
+                        return Ok(transfer.unwrap_or(chunk));
+                    }
+
+                    match transfer.as_mut() {
+                        Some(transfer) => {
+                            transfer.value.extend_from_slice(&chunk.value);
+                            transfer.value_len += chunk.value_len;
+                        },
+                        None => transfer = Some(chunk),
+                    }
+
+                    deadline = Instant::now() + self.timeout();
                 },
+                _ => (),
             }
         }
     }
 
-    fn set_contents(&self, _: String) -> Result<()> {
-        todo!()
+    /// Read the selection as text, falling back to the legacy text targets for owners which
+    /// don't support UTF-8.
+    async fn load_text(&self, lossy: bool) -> Result<String> {
+        let targets =
+            [self.atoms.utf8_string, self.atoms.string, self.atoms.text, self.atoms.compound_text];
+        for &target in &targets {
+            match self.convert_selection(target).await {
+                Ok(reply) => return self.decode_text(reply.type_, reply.value, lossy),
+                Err(ClipboardError::FormatNotAvailable) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(ClipboardError::FormatNotAvailable)
     }
 
-    fn get_content_types(&self) -> Result<Vec<ContentType>> {
-        let cookie = self.connection.convert_selection(
-            self.window,
-            self.clipboard,
-            self.targets,
-            self.property,
-            current_time(),
+    /// Decode text according to the type of the property it was stored in.
+    ///
+    /// The owner picks the type when converting to `TEXT`, and anything but `STRING` and
+    /// `COMPOUND_TEXT` is assumed to be UTF-8.
+    fn decode_text(&self, type_: Atom, data: Vec<u8>, lossy: bool) -> Result<String> {
+        if type_ == self.atoms.string {
+            Ok(data.into_iter().map(char::from).collect())
+        } else if type_ == self.atoms.compound_text {
+            compound_text::decode(&data, lossy)
+        } else if lossy {
+            Ok(String::from_utf8_lossy(&data).into_owned())
+        } else {
+            Ok(String::from_utf8(data)?)
+        }
+    }
+
+    /// Get the targets offering the data of each content type in `map`.
+    fn content_targets(
+        &self,
+        map: HashMap<ContentType, Vec<u8>>,
+    ) -> Result<HashMap<Atom, (Atom, Vec<u8>)>> {
+        let mut targets = HashMap::new();
+        for (ct, data) in map {
+            if ct == ContentType::Text {
+                self.insert_text_targets(&mut targets, data);
+            } else {
+                let target = intern_atom(&self.connection, &ct.name(Namespace::X11))?;
+                targets.insert(target, (target, data));
+            }
+        }
+        Ok(targets)
+    }
+
+    /// Offer UTF-8 encoded `text` through all of the common text targets.
+    fn insert_text_targets(&self, targets: &mut HashMap<Atom, (Atom, Vec<u8>)>, text: Vec<u8>) {
+        let latin1 = to_latin1(&String::from_utf8_lossy(&text));
+        targets.insert(self.atoms.string, (self.atoms.string, latin1));
+        targets.insert(self.atoms.text, (self.atoms.utf8_string, text.clone()));
+        targets.insert(self.atoms.text_plain_utf8, (self.atoms.text_plain_utf8, text.clone()));
+        targets.insert(self.atoms.utf8_string, (self.atoms.utf8_string, text));
+    }
+
+    /// Take ownership of the selection, offering `targets`.
+    async fn acquire(&self, targets: HashMap<Atom, (Atom, Vec<u8>)>) -> Result<()> {
+        let time = {
+            let requestor = self.events.requestor()?;
+            self.server_time(&requestor).await?
+        };
+        self.owner.set_contents(self.selection, targets, time)
+    }
+
+    /// Get the list of content types offered by the selection owner.
+    async fn load_content_types(&self) -> Result<Vec<ContentType>> {
+        let reply = match self.convert_selection(self.atoms.targets).await {
+            Ok(reply) => reply,
+            Err(ClipboardError::Empty) => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut cts = Vec::new();
+        for atom in reply.value32().ok_or("invalid response format for targets")? {
+            let name = atom_name(&self.connection, atom)?;
+            if META_TARGETS.contains(&name.as_str()) {
+                continue;
+            }
+
+            // Several targets may map to the same content type.
+            let ct = ContentType::Custom(name).normalize(Namespace::X11);
+            if !cts.contains(&ct) {
+                cts.push(ct);
+            }
+        }
+        Ok(cts)
+    }
+
+    /// Get the data offered for a content type.
+    async fn load_content(&self, ct: &ContentType) -> Result<Vec<u8>> {
+        let target = intern_atom(&self.connection, &ct.name(Namespace::X11))?;
+        Ok(self.convert_selection(target).await?.value)
+    }
+
+    /// Read an image offered as a `PIXMAP`.
+    #[cfg(feature = "image-data")]
+    async fn load_pixmap(&self) -> Result<ImageData> {
+        let target = intern_atom(&self.connection, "PIXMAP")?;
+        let reply = self.convert_selection(target).await?;
+        let pixmap = reply
+            .value32()
+            .and_then(|mut pixmaps| pixmaps.next())
+            .ok_or("invalid response format for pixmap")?;
+
+        let geometry = self.connection.get_geometry(pixmap)?.reply()?;
+        let image = self
+            .connection
+            .get_image(ImageFormat::Z_PIXMAP, pixmap, 0, 0, geometry.width, geometry.height, !0)?
+            .reply()?;
+        pixmap::decode(self.connection.setup(), &geometry, &image)
+    }
+
+    /// Get the current server time.
+    ///
+    /// There is no request for this, so a zero-length append to a property of the requestor
+    /// window is used to generate a `PropertyNotify` event, which carries the time of the change.
+    async fn server_time(&self, requestor: &Requestor<'_>) -> Result<Timestamp> {
+        self.connection.change_property32(
+            PropMode::APPEND,
+            requestor.window,
+            self.atoms.timestamp_property,
+            self.atoms.integer,
+            &[],
         )?;
-        cookie.check()?;
         self.connection.flush()?;
 
+        let deadline = Instant::now() + self.timeout();
         loop {
-            let event = self.connection.wait_for_event()?;
-            match event {
-                Event::SelectionNotify(_ev) => {
-                    let val =
-                        self.get_full_property(false, self.window, self.property, self.atom)?;
-                    let mut cts = Vec::new();
-                    for atom in val.value32().ok_or("invalid response format for targets")? {
-                        // TODO convert atom names correctly here
-                        cts.push(ContentType::Custom(atom_name(&self.connection, atom)?))
-                    }
-                    return Ok(cts);
+            match requestor.next_event(deadline).await? {
+                Event::PropertyNotify(ev) if ev.atom == self.atoms.timestamp_property => {
+                    return Ok(ev.time)
+                },
+                _ => (),
+            }
+        }
+    }
+}
+
+impl<S> ClipboardProvider for X11RbClipboardContext<S>
+where
+    S: Selection,
+{
+    fn get_contents(&self) -> Result<String> {
+        block_on(self.requester.load_text(false))
+    }
+
+    fn get_contents_lossy(&self) -> Result<String> {
+        block_on(self.requester.load_text(true))
+    }
+
+    fn set_contents(&self, data: String) -> Result<()> {
+        let mut targets = HashMap::new();
+        self.requester.insert_text_targets(&mut targets, data.into_bytes());
+
+        block_on(self.requester.acquire(targets))
+    }
+
+    fn get_content_types(&self) -> Result<Vec<ContentType>> {
+        block_on(self.requester.load_content_types())
+    }
+
+    fn get_content_for_type(&self, ct: &ContentType) -> Result<Vec<u8>> {
+        block_on(self.requester.load_content(ct))
+    }
+
+    fn set_content_types(&self, map: HashMap<ContentType, Vec<u8>>) -> Result<()> {
+        let targets = self.requester.content_targets(map)?;
+        block_on(self.requester.acquire(targets))
+    }
+
+    /// Images are also read from a `PIXMAP`, if the owner offers no image file format.
+    #[cfg(feature = "image-data")]
+    fn get_image(&self) -> Result<ImageData> {
+        let offered = block_on(self.requester.load_content_types())?;
+        let load = |ct: &ContentType| block_on(self.requester.load_content(ct));
+        if let Some(image) = image_data::load_image(&offered, load)? {
+            Ok(image)
+        } else if offered.contains(&ContentType::Custom("PIXMAP".into())) {
+            block_on(self.requester.load_pixmap())
+        } else {
+            Err(ClipboardError::FormatNotAvailable)
+        }
+    }
+
+    /// BMP is offered along with PNG, since some X11 applications can only paste the former.
+    #[cfg(feature = "image-data")]
+    fn image_content_types(&self) -> Vec<ContentType> {
+        vec![ContentType::Png, ContentType::Custom("image/bmp".into())]
+    }
+
+    fn watch(&self) -> Result<ClipboardWatch> {
+        let (sender, receiver) = mpsc::channel();
+        let watcher = SelectionWatcher::new(self.requester.selection, sender)?;
+        Ok(ClipboardWatch::new(receiver, watcher))
+    }
+
+    fn normalize_content_type(ct: ContentType) -> ContentType {
+        ct.normalize(Namespace::X11)
+    }
+
+    fn denormalize_content_type(ct: ContentType) -> String {
+        ct.name(Namespace::X11)
+    }
+}
+
+impl<S> Drop for X11RbClipboardContext<S>
+where
+    S: Selection,
+{
+    fn drop(&mut self) {
+        if *self.persist_on_drop.get_mut() {
+            let _ = self.persist();
+        }
+    }
+}
+
+/// Operations wait for the selection owner without blocking, and stop as soon as their future is
+/// dropped. Only the short round trips to the X server itself block the polling thread.
+impl<S> AsyncClipboardProvider for X11RbClipboardContext<S>
+where
+    S: Selection,
+{
+    fn get_contents(&self) -> ClipboardFuture<String> {
+        let requester = self.requester.clone();
+        ClipboardFuture::new(async move { requester.load_text(false).await })
+    }
+
+    fn set_contents(&self, data: String) -> ClipboardFuture<()> {
+        let requester = self.requester.clone();
+        ClipboardFuture::new(async move {
+            let mut targets = HashMap::new();
+            requester.insert_text_targets(&mut targets, data.into_bytes());
+            requester.acquire(targets).await
+        })
+    }
+
+    fn get_content_types(&self) -> ClipboardFuture<Vec<ContentType>> {
+        let requester = self.requester.clone();
+        ClipboardFuture::new(async move { requester.load_content_types().await })
+    }
+
+    fn get_content_for_type(&self, ct: &ContentType) -> ClipboardFuture<Vec<u8>> {
+        let requester = self.requester.clone();
+        let ct = ct.clone();
+        ClipboardFuture::new(async move { requester.load_content(&ct).await })
+    }
+
+    fn set_content_types(&self, map: HashMap<ContentType, Vec<u8>>) -> ClipboardFuture<()> {
+        let requester = self.requester.clone();
+        ClipboardFuture::new(async move {
+            let targets = requester.content_targets(map)?;
+            requester.acquire(targets).await
+        })
+    }
+}
+
+/// State of an outgoing INCR transfer.
+struct IncrTransfer {
+    type_: Atom,
+    data: Vec<u8>,
+    position: usize,
+}
+
+type ConnectionResult<T> = std::result::Result<T, ConnectionError>;
+
+/// Event loop of the selection owner thread.
+struct SelectionServer<'a> {
+    connection: &'a RustConnection,
+    window: Window,
+    atoms: Atoms,
+    contents: &'a Mutex<Option<Contents>>,
+    /// Largest value which is sent in a single property, anything bigger goes through INCR.
+    max_property_size: usize,
+    /// Ongoing INCR transfers, keyed by requestor window and property.
+    transfers: HashMap<(Window, Atom), IncrTransfer>,
+}
+
+impl<'a> SelectionServer<'a> {
+    fn new(
+        connection: &'a RustConnection,
+        window: Window,
+        atoms: Atoms,
+        contents: &'a Mutex<Option<Contents>>,
+    ) -> Self {
+        // Leave room for the ChangeProperty request header.
+        let max_property_size = connection.maximum_request_bytes().saturating_sub(24);
+        Self { connection, window, atoms, contents, max_property_size, transfers: HashMap::new() }
+    }
+
+    /// Serve requests until the owner window is destroyed or the connection breaks.
+    ///
+    /// Errors caused by requestors, like a window destroyed in the middle of a conversion, are
+    /// reported as events and only fail that conversion.
+    fn run(&mut self) {
+        while let Ok(event) = self.connection.wait_for_event() {
+            let result = match event {
+                Event::SelectionRequest(ev) => self.handle_selection_request(&ev),
+                Event::SelectionClear(ev) => {
+                    self.handle_selection_clear(ev.time);
+                    Ok(())
+                },
+                Event::PropertyNotify(ev) if ev.state == Property::DELETE => {
+                    self.continue_incr(ev.window, ev.atom)
                 },
-                _ => {
-                    dbg!("Have event {:?}", event);
+                Event::DestroyNotify(ev) if ev.window == self.window => return,
+                Event::DestroyNotify(ev) => {
+                    // The requestor went away in the middle of an INCR transfer.
+                    self.transfers.retain(|&(requestor, _), _| requestor != ev.window);
+                    Ok(())
+                },
+                _ => Ok(()),
+            };
+
+            if result.is_err() {
+                return;
+            }
+        }
+    }
+
+    fn handle_selection_clear(&mut self, time: Timestamp) {
+        let mut contents = self.contents.lock().unwrap();
+
+        // The clear is stale if we took the selection back after it was sent.
+        let stale = match contents.as_ref() {
+            Some(contents) => time_before(time, contents.acquired),
+            None => return,
+        };
+
+        if !stale {
+            contents.take();
+        }
+    }
+
+    /// Answer a single `ConvertSelection` request from another client.
+    fn handle_selection_request(&mut self, ev: &SelectionRequestEvent) -> ConnectionResult<()> {
+        // Obsolete clients may not specify a property, in which case the target is used instead.
+        let property = if ev.property == NONE { ev.target } else { ev.property };
+
+        let converted = if ev.target == self.atoms.multiple {
+            ev.property != NONE && self.convert_multiple(ev.requestor, property, ev.time)?
+        } else {
+            match self.convert(ev.target, ev.time) {
+                Some((type_, format, data)) => {
+                    self.send_property(ev.requestor, property, type_, format, data)?;
+                    true
+                },
+                None => false,
+            }
+        };
+
+        let notify = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: ev.time,
+            requestor: ev.requestor,
+            selection: ev.selection,
+            target: ev.target,
+            property: if converted { property } else { NONE },
+        };
+        self.connection.send_event(false, ev.requestor, EventMask::NO_EVENT, notify)?;
+        self.connection.flush()?;
+
+        Ok(())
+    }
+
+    /// Answer a `MULTIPLE` request, used by clipboard managers to fetch several targets at once.
+    ///
+    /// The requestor's `property` holds pairs of targets and properties. Every target is converted
+    /// into its property, and the pairs which could not be converted get their property replaced
+    /// by `None`.
+    fn convert_multiple(
+        &mut self,
+        requestor: Window,
+        property: Atom,
+        time: Timestamp,
+    ) -> ConnectionResult<bool> {
+        if !self.owns(time) {
+            return Ok(false);
+        }
+
+        let cookie = self.connection.get_property(
+            false,
+            requestor,
+            property,
+            self.atoms.atom_pair,
+            0,
+            PROPERTY_CHUNK_LENGTH,
+        )?;
+        let reply = match cookie.reply() {
+            Ok(reply) => reply,
+            // The requestor went away or passed a bogus property, which fails its conversion
+            // rather than our thread.
+            Err(ReplyError::X11Error(_)) => return Ok(false),
+            Err(ReplyError::ConnectionError(err)) => return Err(err),
+        };
+        let mut pairs: Vec<Atom> = match reply.value32() {
+            Some(pairs) => pairs.collect(),
+            None => return Ok(false),
+        };
+
+        for pair in pairs.chunks_exact_mut(2) {
+            let (target, pair_property) = (pair[0], pair[1]);
+            match self.convert(target, time) {
+                Some((type_, format, data)) if target != self.atoms.multiple => {
+                    self.send_property(requestor, pair_property, type_, format, data)?
                 },
+                _ => pair[1] = NONE,
             }
         }
+
+        self.connection.change_property32(
+            PropMode::REPLACE,
+            requestor,
+            property,
+            self.atoms.atom_pair,
+            &pairs,
+        )?;
+
+        Ok(true)
+    }
+
+    /// Check whether we owned the selection at `time`.
+    fn owns(&self, time: Timestamp) -> bool {
+        self.contents.lock().unwrap().as_ref().filter(|contents| contents.owned_at(time)).is_some()
+    }
+
+    /// Convert our contents to `target`, returning the type, format and data of the property.
+    fn convert(&self, target: Atom, time: Timestamp) -> Option<(Atom, u8, Vec<u8>)> {
+        let contents = self.contents.lock().unwrap();
+        let contents = contents.as_ref().filter(|contents| contents.owned_at(time))?;
+
+        if target == self.atoms.targets {
+            let targets = [self.atoms.targets, self.atoms.timestamp, self.atoms.multiple];
+            let data = targets
+                .iter()
+                .chain(contents.targets.keys())
+                .flat_map(|atom| atom.to_ne_bytes().to_vec())
+                .collect();
+            Some((self.atoms.atom, 32, data))
+        } else if target == self.atoms.timestamp {
+            Some((self.atoms.integer, 32, contents.acquired.to_ne_bytes().to_vec()))
+        } else {
+            let (type_, data) = contents.targets.get(&target)?;
+            Some((*type_, 8, data.clone()))
+        }
+    }
+
+    /// Write a property on the requestor, starting an INCR transfer if it is too big.
+    fn send_property(
+        &mut self,
+        requestor: Window,
+        property: Atom,
+        type_: Atom,
+        format: u8,
+        data: Vec<u8>,
+    ) -> ConnectionResult<()> {
+        if data.len() <= self.max_property_size {
+            let data_len = (data.len() / usize::from(format / 8)) as u32;
+            self.connection.change_property(
+                PropMode::REPLACE,
+                requestor,
+                property,
+                type_,
+                format,
+                data_len,
+                &data,
+            )?;
+            return Ok(());
+        }
+
+        // We need to know when the requestor deleted the property to send the next chunk.
+        let aux = ChangeWindowAttributesAux::new()
+            .event_mask(EventMask::PROPERTY_CHANGE | EventMask::STRUCTURE_NOTIFY);
+        self.connection.change_window_attributes(requestor, &aux)?;
+
+        // The INCR property contains a lower bound of the total size.
+        let size = data.len().min(u32::MAX as usize) as u32;
+        self.connection.change_property32(
+            PropMode::REPLACE,
+            requestor,
+            property,
+            self.atoms.incr,
+            &[size],
+        )?;
+
+        self.transfers.insert((requestor, property), IncrTransfer { type_, data, position: 0 });
+
+        Ok(())
+    }
+
+    /// Send the next chunk of an INCR transfer, after the requestor deleted the previous one.
+    fn continue_incr(&mut self, requestor: Window, property: Atom) -> ConnectionResult<()> {
+        let transfer = match self.transfers.get_mut(&(requestor, property)) {
+            Some(transfer) => transfer,
+            None => return Ok(()),
+        };
+
+        let chunk_size = INCR_CHUNK_SIZE.min(self.max_property_size);
+        let end = transfer.data.len().min(transfer.position + chunk_size);
+        let chunk = &transfer.data[transfer.position..end];
+        self.connection.change_property8(
+            PropMode::REPLACE,
+            requestor,
+            property,
+            transfer.type_,
+            chunk,
+        )?;
+        transfer.position = end;
+
+        // The last, zero-length chunk was just sent.
+        if chunk.is_empty() {
+            self.transfers.remove(&(requestor, property));
+            if self.transfers.keys().all(|&(window, _)| window != requestor) {
+                let aux = ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT);
+                self.connection.change_window_attributes(requestor, &aux)?;
+            }
+        }
+
+        self.connection.flush()?;
+
+        Ok(())
     }
 }
 
+fn create_window(
+    connection: &RustConnection,
+    screen_num: usize,
+    event_mask: EventMask,
+) -> Result<Window> {
+    let screen = &connection.setup().roots[screen_num];
+    let window = connection.generate_id()?;
+
+    let win_aux = CreateWindowAux::new().event_mask(event_mask);
+    let cookie = connection.create_window(
+        screen.root_depth,
+        window,
+        screen.root,
+        0,
+        0,
+        1,
+        1,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        0,
+        &win_aux,
+    )?;
+    cookie.check()?;
+
+    Ok(window)
+}
+
 fn intern_atom(connection: &RustConnection, name: &str) -> Result<Atom> {
     Ok(connection.intern_atom(false, name.as_bytes())?.reply()?.atom)
 }
 
 fn atom_name(connection: &RustConnection, atom: Atom) -> Result<String> {
     let reply = connection.get_atom_name(atom)?.reply()?;
-    String::from_utf8(reply.name).map_err(|e| Box::new(e) as _)
+    String::from_utf8(reply.name).map_err(|err| ClipboardError::Backend(err.into()))
+}
+
+/// Check whether the server time `a` is before `b`, taking wraparound into account.
+fn time_before(a: Timestamp, b: Timestamp) -> bool {
+    (a.wrapping_sub(b) as i32) < 0
 }
 
-fn current_time() -> Timestamp {
-    let start = SystemTime::now();
-    let since_the_epoch = start.duration_since(UNIX_EPOCH).expect("Time went backwards");
-    since_the_epoch.as_secs().try_into().expect("if you're using this past 2k38, hmmmmmmmm")
+/// Encode text for the `STRING` target, which is defined to be ISO Latin-1.
+///
+/// Characters outside of Latin-1 are replaced with `?`.
+fn to_latin1(text: &str) -> Vec<u8> {
+    text.chars().map(|c| if (c as u32) < 0x100 { c as u8 } else { b'?' }).collect()
 }
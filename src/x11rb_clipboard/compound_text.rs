@@ -0,0 +1,156 @@
+//! Decoding of the `COMPOUND_TEXT` encoding.
+//!
+//! Compound text is a subset of ISO 2022, switching between character sets with escape
+//! sequences. Only ASCII, ISO Latin-1 and UTF-8 segments are supported, which covers everything
+//! Xlib produces in UTF-8 locales. Text in other character sets can only be read lossily.
+
+use std::char::REPLACEMENT_CHARACTER;
+use std::str;
+
+use crate::common::{ClipboardError, Result};
+
+const ESC: u8 = 0x1b;
+/// Control Sequence Introducer, used for directionality.
+const CSI: u8 = 0x9b;
+
+/// Character set of the left (GL) or right (GR) half of the code table.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Charset {
+    Ascii,
+    Latin1,
+    /// Any other set, with the number of bytes per character.
+    Unsupported(usize),
+}
+
+impl Charset {
+    /// Decode the character starting with `byte`, with the high bit ignored.
+    fn decode(self, byte: u8) -> Option<char> {
+        match self {
+            Charset::Ascii => Some((byte & 0x7f) as char),
+            Charset::Latin1 => Some((byte | 0x80) as char),
+            Charset::Unsupported(_) => None,
+        }
+    }
+
+    fn width(self) -> usize {
+        match self {
+            Charset::Unsupported(width) => width,
+            _ => 1,
+        }
+    }
+}
+
+/// Decode compound text, replacing unsupported characters with U+FFFD if `lossy` is set.
+pub(super) fn decode(data: &[u8], lossy: bool) -> Result<String> {
+    let mut text = String::with_capacity(data.len());
+    let mut gl = Charset::Ascii;
+    let mut gr = Charset::Latin1;
+    let unsupported = |text: &mut String| {
+        if lossy {
+            text.push(REPLACEMENT_CHARACTER);
+            Ok(())
+        } else {
+            Err(ClipboardError::Backend("unsupported character set in COMPOUND_TEXT".into()))
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let byte = data[i];
+        let charset = match byte {
+            0x21..=0x7e => gl,
+            0xa0..=0xff => gr,
+            ESC => {
+                i += 1 + escape_sequence(&data[i + 1..], &mut text, &mut gl, &mut gr, lossy)?;
+                continue;
+            },
+            // Directionality changes don't affect the logical order of the text.
+            CSI => {
+                i +=
+                    data[i..].iter().position(|&b| b == b']').map_or(data.len() - i, |end| end + 1);
+                continue;
+            },
+            // Space and the control characters have a fixed meaning.
+            _ => {
+                text.push(byte as char);
+                i += 1;
+                continue;
+            },
+        };
+
+        match charset.decode(byte) {
+            Some(c) => text.push(c),
+            None => unsupported(&mut text)?,
+        }
+        i += charset.width();
+    }
+
+    Ok(text)
+}
+
+/// Handle the escape sequence following an `ESC`, returning its length.
+fn escape_sequence(
+    sequence: &[u8],
+    text: &mut String,
+    gl: &mut Charset,
+    gr: &mut Charset,
+    lossy: bool,
+) -> Result<usize> {
+    match sequence {
+        // UTF-8 segment, terminated by `ESC % @`.
+        [b'%', b'G', rest @ ..] => {
+            let end = rest.windows(3).position(|w| w == b"\x1b%@").unwrap_or(rest.len());
+            match str::from_utf8(&rest[..end]) {
+                Ok(segment) => text.push_str(segment),
+                Err(_) if lossy => text.push_str(&String::from_utf8_lossy(&rest[..end])),
+                Err(_) => return Err(ClipboardError::InvalidUtf8),
+            }
+            Ok((2 + end + 3).min(sequence.len()))
+        },
+        // Extended segment in some other encoding, prefixed with its length.
+        [b'%', b'/', _, m, l, ..] => {
+            if !lossy {
+                return Err("unsupported extended segment in COMPOUND_TEXT".into());
+            }
+            text.push(REPLACEMENT_CHARACTER);
+            let len = (*m as usize & 0x7f) * 128 + (*l as usize & 0x7f);
+            Ok((5 + len).min(sequence.len()))
+        },
+        // 94 character sets.
+        [b'(', b'B', ..] => {
+            *gl = Charset::Ascii;
+            Ok(2)
+        },
+        [b')', b'B', ..] => {
+            *gr = Charset::Ascii;
+            Ok(2)
+        },
+        [b'(', _, ..] => {
+            *gl = Charset::Unsupported(1);
+            Ok(2)
+        },
+        [b')', _, ..] => {
+            *gr = Charset::Unsupported(1);
+            Ok(2)
+        },
+        // 96 character sets, which can only be used in GR.
+        [b'-', b'A', ..] => {
+            *gr = Charset::Latin1;
+            Ok(2)
+        },
+        [b'-', _, ..] => {
+            *gr = Charset::Unsupported(1);
+            Ok(2)
+        },
+        // 94^2 character sets.
+        [b'$', b'(', _, ..] => {
+            *gl = Charset::Unsupported(2);
+            Ok(3)
+        },
+        [b'$', b')', _, ..] => {
+            *gr = Charset::Unsupported(2);
+            Ok(3)
+        },
+        _ => Err("invalid escape sequence in COMPOUND_TEXT".into()),
+    }
+}
@@ -0,0 +1,92 @@
+//! Decoding of images offered as a `PIXMAP`, which older clients use instead of an image file.
+//!
+//! The selection only holds the ID of the pixmap, whose pixels are then read from the server in
+//! its own layout. Only true color pixmaps are supported, which is what modern displays use.
+
+use x11rb::protocol::xproto::{GetGeometryReply, GetImageReply, ImageOrder, Setup, VisualClass};
+
+use crate::common::Result;
+use crate::image_data::ImageData;
+
+/// Convert the `Z_PIXMAP` image of a pixmap to RGBA pixels.
+pub fn decode(
+    setup: &Setup,
+    geometry: &GetGeometryReply,
+    image: &GetImageReply,
+) -> Result<ImageData> {
+    let unsupported = || format!("unsupported pixmap of depth {}", geometry.depth);
+
+    let format = setup
+        .pixmap_formats
+        .iter()
+        .find(|format| format.depth == geometry.depth)
+        .ok_or_else(unsupported)?;
+    let visual = setup
+        .roots
+        .iter()
+        .filter(|screen| screen.root == geometry.root)
+        .flat_map(|screen| &screen.allowed_depths)
+        .filter(|depth| depth.depth == geometry.depth)
+        .flat_map(|depth| &depth.visuals)
+        .find(|visual| visual.class == VisualClass::TRUE_COLOR)
+        .ok_or_else(unsupported)?;
+    let bytes_per_pixel = match format.bits_per_pixel {
+        24 => 3,
+        32 => 4,
+        _ => return Err(unsupported().into()),
+    };
+
+    let width = usize::from(geometry.width);
+    let height = usize::from(geometry.height);
+    let pad = usize::from(format.scanline_pad);
+    let stride = (width * usize::from(format.bits_per_pixel) + pad - 1) / pad * pad / 8;
+    if image.data.len() < stride * height {
+        return Err("pixmap image is truncated".into());
+    }
+
+    // Bits outside of the color masks hold the alpha channel of 32 bit deep pixmaps.
+    let alpha_mask = match geometry.depth {
+        32 => !(visual.red_mask | visual.green_mask | visual.blue_mask),
+        _ => 0,
+    };
+
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for row in image.data.chunks(stride).take(height) {
+        for bytes in row[..width * bytes_per_pixel].chunks(bytes_per_pixel) {
+            let pixel = if setup.image_byte_order == ImageOrder::LSB_FIRST {
+                bytes.iter().rev().fold(0, |pixel, &byte| pixel << 8 | u32::from(byte))
+            } else {
+                bytes.iter().fold(0, |pixel, &byte| pixel << 8 | u32::from(byte))
+            };
+
+            let alpha = if alpha_mask == 0 { 255 } else { channel(pixel, alpha_mask) };
+            for &mask in &[visual.red_mask, visual.green_mask, visual.blue_mask] {
+                rgba.push(unpremultiply(channel(pixel, mask), alpha));
+            }
+            rgba.push(alpha);
+        }
+    }
+
+    Ok(ImageData { width, height, rgba })
+}
+
+/// Scale the bits of `pixel` selected by `mask` to 8 bits.
+fn channel(pixel: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let shift = mask.trailing_zeros();
+    let max = u64::from(mask >> shift);
+    let value = u64::from((pixel & mask) >> shift);
+    (value * 255 / max) as u8
+}
+
+/// Undo the premultiplication of a color channel by alpha, which X uses for translucent pixels.
+fn unpremultiply(value: u8, alpha: u8) -> u8 {
+    match alpha {
+        0 => 0,
+        255 => value,
+        _ => (u16::from(value) * 255 / u16::from(alpha)).min(255) as u8,
+    }
+}
@@ -0,0 +1,195 @@
+//! Delivery of the events of the requesting connection to the operations waiting for them.
+//!
+//! Every operation gets a window of its own, which it uses as the requestor of its conversions.
+//! A single thread reads the events of the connection and queues them for the window they were
+//! sent to. Operations never read the connection themselves: x11rb queues the events which arrive
+//! while another thread waits for a reply, so waiting on the socket could miss them.
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{ConnectionExt as _, EventMask, Window};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+
+use super::create_window;
+use super::timer::Timer;
+use crate::common::{ClipboardError, Result};
+
+/// Events received for a requestor window, which were not consumed yet.
+#[derive(Default)]
+struct Inbox {
+    events: VecDeque<Event>,
+    waker: Option<Waker>,
+}
+
+#[derive(Default)]
+struct Inboxes {
+    windows: HashMap<Window, Inbox>,
+    /// Set once the router stopped, after which no events arrive anymore.
+    closed: bool,
+}
+
+pub struct EventRouter {
+    connection: Arc<RustConnection>,
+    screen_num: usize,
+    /// Window whose destruction stops the routing thread.
+    window: Window,
+    inboxes: Arc<Mutex<Inboxes>>,
+    timer: Timer,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl EventRouter {
+    pub fn new(connection: Arc<RustConnection>, screen_num: usize) -> Result<Self> {
+        let window = create_window(&connection, screen_num, EventMask::STRUCTURE_NOTIFY)?;
+        let inboxes = Arc::new(Mutex::new(Inboxes::default()));
+        let timer = Timer::new()?;
+        let thread = {
+            let connection = connection.clone();
+            let inboxes = inboxes.clone();
+            thread::Builder::new()
+                .name("copypasta-x11rb-events".into())
+                .spawn(move || route_events(&connection, window, &inboxes))?
+        };
+
+        Ok(Self { connection, screen_num, window, inboxes, timer, thread: Some(thread) })
+    }
+
+    /// Create a window receiving the events of a single operation.
+    pub fn requestor(&self) -> Result<Requestor<'_>> {
+        // Property changes are needed for server timestamps and INCR transfers.
+        let window = create_window(&self.connection, self.screen_num, EventMask::PROPERTY_CHANGE)?;
+        let requestor = Requestor { router: self, window };
+
+        let registered = {
+            let mut inboxes = self.inboxes.lock().unwrap();
+            if !inboxes.closed {
+                inboxes.windows.insert(window, Inbox::default());
+            }
+            !inboxes.closed
+        };
+
+        if registered {
+            Ok(requestor)
+        } else {
+            Err(ClipboardError::ConnectionLost)
+        }
+    }
+}
+
+impl Drop for EventRouter {
+    fn drop(&mut self) {
+        // Destroying the window makes the routing thread exit.
+        let _ = self.connection.destroy_window(self.window);
+        let _ = self.connection.flush();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Queue events for the requestor window they were sent to, until `window` is destroyed.
+fn route_events(connection: &RustConnection, window: Window, inboxes: &Mutex<Inboxes>) {
+    while let Ok(event) = connection.wait_for_event() {
+        let requestor = match &event {
+            Event::SelectionNotify(ev) => ev.requestor,
+            Event::PropertyNotify(ev) => ev.window,
+            Event::DestroyNotify(ev) if ev.window == window => break,
+            _ => continue,
+        };
+
+        // Events for windows of finished operations are dropped.
+        let mut inboxes = inboxes.lock().unwrap();
+        if let Some(inbox) = inboxes.windows.get_mut(&requestor) {
+            inbox.events.push_back(event);
+            if let Some(waker) = inbox.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    let mut inboxes = inboxes.lock().unwrap();
+    inboxes.closed = true;
+    for inbox in inboxes.windows.values_mut() {
+        if let Some(waker) = inbox.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Window of a single operation, destroyed along with its properties once the operation is over.
+pub struct Requestor<'a> {
+    router: &'a EventRouter,
+    pub window: Window,
+}
+
+impl Requestor<'_> {
+    /// Wait for the next event sent to the window.
+    ///
+    /// Fails with [`ClipboardError::Timeout`] once `deadline` has passed.
+    pub fn next_event(&self, deadline: Instant) -> NextEvent<'_> {
+        NextEvent { requestor: self, deadline, timer_waker: None }
+    }
+}
+
+impl Drop for Requestor<'_> {
+    fn drop(&mut self) {
+        self.router.inboxes.lock().unwrap().windows.remove(&self.window);
+
+        let _ = self.router.connection.destroy_window(self.window);
+        let _ = self.router.connection.flush();
+    }
+}
+
+/// Future returned by [`Requestor::next_event`].
+pub struct NextEvent<'a> {
+    requestor: &'a Requestor<'a>,
+    deadline: Instant,
+    /// Waker which the timer wakes at the deadline.
+    timer_waker: Option<Waker>,
+}
+
+impl Future for NextEvent<'_> {
+    type Output = Result<Event>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<Event>> {
+        let router = self.requestor.router;
+
+        let mut inboxes = router.inboxes.lock().unwrap();
+        let closed = inboxes.closed;
+        let inbox = match inboxes.windows.get_mut(&self.requestor.window) {
+            Some(inbox) => inbox,
+            None => return Poll::Ready(Err(ClipboardError::ConnectionLost)),
+        };
+
+        if let Some(event) = inbox.events.pop_front() {
+            return Poll::Ready(Ok(event));
+        } else if closed {
+            return Poll::Ready(Err(ClipboardError::ConnectionLost));
+        } else if Instant::now() >= self.deadline {
+            return Poll::Ready(Err(ClipboardError::Timeout));
+        }
+
+        inbox.waker = Some(cx.waker().clone());
+        drop(inboxes);
+
+        let scheduled = match &self.timer_waker {
+            Some(waker) => waker.will_wake(cx.waker()),
+            None => false,
+        };
+        if !scheduled {
+            router.timer.wake_at(self.deadline, cx.waker().clone());
+            self.timer_waker = Some(cx.waker().clone());
+        }
+
+        Poll::Pending
+    }
+}
@@ -0,0 +1,85 @@
+//! Wakeups of futures waiting for events which may never arrive.
+//!
+//! Events are delivered by the router thread, which can't stop waiting on the connection to
+//! notice that a deadline passed. A separate thread wakes the futures instead, so they can fail
+//! with a timeout.
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::Waker;
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+use crate::common::Result;
+
+pub struct Timer {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    wakeups: Mutex<Wakeups>,
+    changed: Condvar,
+}
+
+#[derive(Default)]
+struct Wakeups {
+    pending: Vec<(Instant, Waker)>,
+    exit: bool,
+}
+
+impl Timer {
+    pub fn new() -> Result<Self> {
+        let shared =
+            Arc::new(Shared { wakeups: Mutex::new(Wakeups::default()), changed: Condvar::new() });
+        let thread = {
+            let shared = shared.clone();
+            thread::Builder::new()
+                .name("copypasta-x11rb-timer".into())
+                .spawn(move || run(&shared))?
+        };
+
+        Ok(Self { shared, thread: Some(thread) })
+    }
+
+    /// Wake `waker` once `deadline` has passed.
+    pub fn wake_at(&self, deadline: Instant, waker: Waker) {
+        self.shared.wakeups.lock().unwrap().pending.push((deadline, waker));
+        self.shared.changed.notify_one();
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.shared.wakeups.lock().unwrap().exit = true;
+        self.shared.changed.notify_one();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(shared: &Shared) {
+    let mut wakeups = shared.wakeups.lock().unwrap();
+    while !wakeups.exit {
+        let now = Instant::now();
+        let (expired, pending): (Vec<_>, Vec<_>) =
+            wakeups.pending.drain(..).partition(|(deadline, _)| *deadline <= now);
+        wakeups.pending = pending;
+
+        // Wakers may run arbitrary code, which must not block further wakeups from being added.
+        if !expired.is_empty() {
+            drop(wakeups);
+            for (_, waker) in expired {
+                waker.wake();
+            }
+            wakeups = shared.wakeups.lock().unwrap();
+            continue;
+        }
+
+        wakeups = match wakeups.pending.iter().map(|(deadline, _)| *deadline).min() {
+            Some(deadline) => shared.changed.wait_timeout(wakeups, deadline - now).unwrap().0,
+            None => shared.changed.wait(wakeups).unwrap(),
+        };
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+use std::thread::{self, Thread};
+
+use copypasta::memory_clipboard::InMemoryClipboardContext;
+use copypasta::{
+    AsyncClipboardContext, AsyncClipboardProvider, ClipboardError, ClipboardProvider, ContentType,
+};
+
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Minimal executor, parking the current thread until the future is ready.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Arc::new(ThreadWaker(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn set_and_get() {
+    let ctx = AsyncClipboardContext::new(InMemoryClipboardContext::new().unwrap()).unwrap();
+
+    block_on(ctx.set_contents("async".into())).unwrap();
+    assert_eq!(block_on(ctx.get_contents()).unwrap(), "async");
+}
+
+#[test]
+fn shares_state_with_blocking_context() {
+    let blocking = InMemoryClipboardContext::new().unwrap();
+    let ctx = AsyncClipboardContext::new(blocking.clone()).unwrap();
+
+    blocking.set_contents("blocking".into()).unwrap();
+    assert_eq!(block_on(ctx.get_contents()).unwrap(), "blocking");
+
+    let mut map = HashMap::new();
+    map.insert(ContentType::Html, b"<i>async</i>".to_vec());
+    block_on(ctx.set_content_types(map)).unwrap();
+    assert_eq!(block_on(ctx.get_content_types()).unwrap(), [ContentType::Html]);
+    assert_eq!(blocking.get_content_for_type(&ContentType::Html).unwrap(), b"<i>async</i>");
+}
+
+#[test]
+fn errors() {
+    let ctx = AsyncClipboardContext::new(InMemoryClipboardContext::new().unwrap()).unwrap();
+
+    assert!(matches!(block_on(ctx.get_contents()), Err(ClipboardError::Empty)));
+
+    block_on(ctx.set_contents("text".into())).unwrap();
+    let result = block_on(ctx.get_content_for_type(&ContentType::Png));
+    assert!(matches!(result, Err(ClipboardError::FormatNotAvailable)));
+}
+
+#[test]
+fn cancellation() {
+    let blocking = InMemoryClipboardContext::new().unwrap();
+    let ctx = AsyncClipboardContext::new(blocking.clone()).unwrap();
+
+    // Operations run in order, so once the read completes the dropped write was either applied or
+    // skipped, but nothing else happened in the meantime.
+    drop(ctx.set_contents("cancelled".into()));
+    match block_on(ctx.get_contents()) {
+        Ok(text) => assert_eq!(text, "cancelled"),
+        Err(err) => assert!(matches!(err, ClipboardError::Empty)),
+    }
+}
+
+#[test]
+fn concurrent_futures() {
+    let ctx = AsyncClipboardContext::new(InMemoryClipboardContext::new().unwrap()).unwrap();
+
+    let write = ctx.set_contents("first".into());
+    let read = ctx.get_contents();
+    block_on(write).unwrap();
+    assert_eq!(block_on(read).unwrap(), "first");
+}
@@ -0,0 +1,213 @@
+#![cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    ))
+))]
+
+mod common;
+
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use copypasta::command_clipboard::{CommandClipboardContext, Commands, Selection};
+use copypasta::{ClipboardError, ClipboardProvider, ContentType};
+
+use common::TempDir;
+
+/// Stand-in for `wl-copy`, storing the data and its type next to the script.
+const WL_COPY: &str = r#"#!/bin/sh
+PATH=/bin:/usr/bin
+dir=$(dirname "$0"); selection=clipboard; type=text/plain
+while [ $# -gt 0 ]; do
+    case "$1" in
+        --primary) selection=primary ;;
+        --type) type=$2; shift ;;
+    esac
+    shift
+done
+printf '%s\n' "$type" > "$dir/$selection.types"
+cat > "$dir/$selection.data"
+"#;
+
+/// Stand-in for `wl-paste`.
+const WL_PASTE: &str = r#"#!/bin/sh
+PATH=/bin:/usr/bin
+dir=$(dirname "$0"); selection=clipboard; type=; list=
+while [ $# -gt 0 ]; do
+    case "$1" in
+        --primary) selection=primary ;;
+        --type) type=$2; shift ;;
+        --list-types) list=1 ;;
+    esac
+    shift
+done
+[ -f "$dir/$selection.data" ] || { echo "Nothing is copied" >&2; exit 1; }
+[ -n "$list" ] && exec cat "$dir/$selection.types"
+if [ -n "$type" ] && [ "$type" != "$(cat "$dir/$selection.types")" ]; then
+    echo "No suitable type of content copied" >&2; exit 1
+fi
+cat "$dir/$selection.data"
+"#;
+
+/// Stand-in for `xclip`.
+const XCLIP: &str = r#"#!/bin/sh
+PATH=/bin:/usr/bin
+dir=$(dirname "$0"); selection=primary; target=UTF8_STRING; mode=in
+while [ $# -gt 0 ]; do
+    case "$1" in
+        -selection) selection=$2; shift ;;
+        -target) target=$2; shift ;;
+        -in) mode=in ;;
+        -out) mode=out ;;
+    esac
+    shift
+done
+if [ $mode = in ]; then
+    printf 'TARGETS\n%s\n' "$target" > "$dir/$selection.types"
+    exec cat > "$dir/$selection.data"
+fi
+[ "$target" = TARGETS ] && exec cat "$dir/$selection.types"
+grep -qx "$target" "$dir/$selection.types" || { echo "Error: target $target not available" >&2; exit 1; }
+cat "$dir/$selection.data"
+"#;
+
+/// Stand-in for `xsel`.
+const XSEL: &str = r#"#!/bin/sh
+PATH=/bin:/usr/bin
+dir=$(dirname "$0"); selection=primary; mode=output
+for arg in "$@"; do
+    case "$arg" in
+        --clipboard) selection=clipboard ;;
+        --input) mode=input ;;
+    esac
+done
+if [ $mode = input ]; then exec cat > "$dir/$selection.data"; fi
+cat "$dir/$selection.data"
+"#;
+
+/// Create a directory with the stub scripts.
+fn stubs(name: &str) -> TempDir {
+    let dir = TempDir::new(&format!("command-{}", name));
+    fs::create_dir_all(&*dir).unwrap();
+
+    for (program, script) in
+        [("wl-copy", WL_COPY), ("wl-paste", WL_PASTE), ("xclip", XCLIP), ("xsel", XSEL)].iter()
+    {
+        let path = dir.join(program);
+        fs::write(&path, script).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    dir
+}
+
+/// Point the commands at the stubs in `dir`.
+fn stub_commands(dir: &Path, mut commands: Commands) -> CommandClipboardContext {
+    let templates = vec![&mut commands.copy, &mut commands.paste]
+        .into_iter()
+        .chain(commands.copy_type.as_mut())
+        .chain(commands.paste_type.as_mut())
+        .chain(commands.list_types.as_mut());
+    for template in templates {
+        template[0] = dir.join(&template[0]).to_str().unwrap().to_owned();
+    }
+    CommandClipboardContext::with_commands(commands)
+}
+
+#[test]
+fn wl_clipboard() {
+    let dir = stubs("wl-clipboard");
+    let ctx = stub_commands(&dir, Commands::wl_clipboard(Selection::Clipboard));
+
+    assert!(matches!(ctx.get_contents(), Err(ClipboardError::Empty)));
+
+    ctx.set_contents("Hello, world!".into()).unwrap();
+    assert_eq!(ctx.get_contents().unwrap(), "Hello, world!");
+    assert_eq!(ctx.get_content_types().unwrap(), [ContentType::Text]);
+
+    let mut map = HashMap::new();
+    map.insert(ContentType::Html, b"<b>bold</b>".to_vec());
+    ctx.set_content_types(map).unwrap();
+    assert_eq!(ctx.get_content_types().unwrap(), [ContentType::Html]);
+    assert_eq!(ctx.get_content_for_type(&ContentType::Html).unwrap(), b"<b>bold</b>");
+    assert!(matches!(
+        ctx.get_content_for_type(&ContentType::Png),
+        Err(ClipboardError::FormatNotAvailable)
+    ));
+
+    let primary = stub_commands(&dir, Commands::wl_clipboard(Selection::Primary));
+    primary.set_contents("primary".into()).unwrap();
+    assert_eq!(primary.get_contents().unwrap(), "primary");
+    assert_eq!(ctx.get_content_for_type(&ContentType::Html).unwrap(), b"<b>bold</b>");
+}
+
+#[test]
+fn xclip() {
+    let dir = stubs("xclip");
+    let ctx = stub_commands(&dir, Commands::xclip(Selection::Clipboard));
+
+    ctx.set_contents("ünïcödé".into()).unwrap();
+    assert_eq!(ctx.get_contents().unwrap(), "ünïcödé");
+    assert_eq!(ctx.get_content_types().unwrap(), [ContentType::Text]);
+
+    let custom = ContentType::Custom("application/x-copypasta-test".into());
+    let mut map = HashMap::new();
+    map.insert(custom.clone(), vec![0, 1, 2, 255]);
+    ctx.set_content_types(map).unwrap();
+    assert_eq!(ctx.get_content_types().unwrap(), vec![custom.clone()]);
+    assert_eq!(ctx.get_content_for_type(&custom).unwrap(), [0, 1, 2, 255]);
+    assert!(matches!(
+        ctx.get_content_for_type(&ContentType::Html),
+        Err(ClipboardError::FormatNotAvailable)
+    ));
+}
+
+#[test]
+fn xsel() {
+    let dir = stubs("xsel");
+    let ctx = stub_commands(&dir, Commands::xsel(Selection::Clipboard));
+
+    ctx.set_contents("text only".into()).unwrap();
+    assert_eq!(ctx.get_contents().unwrap(), "text only");
+    assert!(matches!(ctx.get_content_types(), Err(ClipboardError::Unsupported)));
+    assert!(matches!(
+        ctx.get_content_for_type(&ContentType::Html),
+        Err(ClipboardError::Unsupported)
+    ));
+}
+
+#[test]
+fn multiple_content_types() {
+    let dir = stubs("multiple");
+    let ctx = stub_commands(&dir, Commands::wl_clipboard(Selection::Clipboard));
+
+    let mut map = HashMap::new();
+    map.insert(ContentType::Html, b"<b>bold</b>".to_vec());
+    map.insert(ContentType::Text, b"bold".to_vec());
+    ctx.set_content_types(map).unwrap();
+
+    // Only the richest content type is written.
+    assert_eq!(ctx.get_content_types().unwrap(), [ContentType::Html]);
+    assert_eq!(ctx.get_content_for_type(&ContentType::Html).unwrap(), b"<b>bold</b>");
+
+    ctx.set_html("<i>html</i>", Some("text")).unwrap();
+    assert_eq!(ctx.get_content_for_type(&ContentType::Html).unwrap(), b"<i>html</i>");
+
+    assert!(matches!(ctx.set_content_types(HashMap::new()), Err(ClipboardError::Unsupported)));
+}
+
+#[test]
+fn missing_program() {
+    let ctx = CommandClipboardContext::with_commands(Commands {
+        copy: vec!["/nonexistent/copypasta-copy".into()],
+        ..Commands::xsel(Selection::Clipboard)
+    });
+
+    assert!(matches!(ctx.set_contents("text".into()), Err(ClipboardError::Backend(_))));
+}
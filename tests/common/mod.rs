@@ -0,0 +1,30 @@
+//! Helpers shared by the integration tests.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// Path for a test in the temporary directory, which is removed with its contents on drop.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Reserve a path for the test `name`, without creating it.
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("copypasta-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        TempDir(dir)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
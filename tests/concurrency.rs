@@ -1,7 +1,31 @@
 use copypasta::{ClipboardContext, ClipboardProvider};
 
+/// Context for the clipboard of the display, if there is one.
+///
+/// On Linux the fallbacks without a display would go through tmux, and overwrite the buffers of
+/// the session the tests are run in, or can't hold any contents at all.
+fn display_context() -> Option<ClipboardContext> {
+    let ctx = ClipboardContext::new().unwrap();
+    #[cfg(all(
+        unix,
+        not(any(
+            target_os = "macos",
+            target_os = "android",
+            target_os = "ios",
+            target_os = "emscripten"
+        ))
+    ))]
+    {
+        use copypasta::linux_clipboard::LinuxClipboardContext;
+        if matches!(ctx, LinuxClipboardContext::Tmux(_) | LinuxClipboardContext::Nop(_)) {
+            return None;
+        }
+    }
+    Some(ctx)
+}
+
 fn some_other_fn() {
-    let mut ctx = ClipboardContext::new().unwrap();
+    let ctx = display_context().unwrap();
     ctx.get_contents().unwrap();
 }
 
@@ -12,7 +36,10 @@ mod tests {
     #[test]
     fn foo() {
         // TODO rewrite this cleanly by spawning threads from one place
-        let mut ctx = ClipboardContext::new().unwrap();
+        let ctx = match display_context() {
+            Some(ctx) => ctx,
+            None => return,
+        };
         ctx.set_contents("Dummy".into()).unwrap();
         ctx.get_contents().unwrap();
 
@@ -21,7 +48,10 @@ mod tests {
 
     #[test]
     fn bar() {
-        let mut ctx = ClipboardContext::new().unwrap();
+        let ctx = match display_context() {
+            Some(ctx) => ctx,
+            None => return,
+        };
         ctx.set_contents("Dummy".into()).unwrap();
         ctx.get_contents().unwrap();
 
@@ -0,0 +1,51 @@
+use copypasta::{register_content_type, ContentType, ContentTypeNames, Namespace};
+
+#[test]
+fn builtin_names() {
+    assert_eq!(ContentType::Text.name(Namespace::Mime), "text/plain;charset=utf-8");
+    assert_eq!(ContentType::Text.name(Namespace::Uti), "public.utf8-plain-text");
+    assert_eq!(ContentType::Text.name(Namespace::Windows), "CF_UNICODETEXT");
+    assert_eq!(ContentType::Text.name(Namespace::X11), "UTF8_STRING");
+    assert_eq!(ContentType::Html.name(Namespace::Uti), "public.html");
+    assert_eq!(ContentType::Png.name(Namespace::Windows), "PNG");
+    assert_eq!(ContentType::Custom("foo".into()).name(Namespace::X11), "foo");
+}
+
+#[test]
+fn builtin_lookup() {
+    assert_eq!(ContentType::from_name(Namespace::Uti, "public.png"), ContentType::Png);
+    assert_eq!(ContentType::from_name(Namespace::Mime, "text/plain"), ContentType::Text);
+    assert_eq!(ContentType::from_name(Namespace::Windows, "HTML Format"), ContentType::Html);
+
+    // Names of other namespaces are recognized too.
+    assert_eq!(ContentType::from_name(Namespace::Mime, "UTF8_STRING"), ContentType::Text);
+    assert_eq!(ContentType::from_name(Namespace::X11, "text/uri-list"), ContentType::Url);
+
+    assert_eq!(ContentType::from_any_name("UTF8_STRING"), ContentType::Text);
+    assert_eq!(ContentType::from_any_name("public.rtf"), ContentType::Rtf);
+    assert_eq!(ContentType::from_any_name("image/bmp"), ContentType::Custom("image/bmp".into()));
+
+    assert_eq!(
+        ContentType::from_name(Namespace::Mime, "image/bmp"),
+        ContentType::Custom("image/bmp".into())
+    );
+}
+
+#[test]
+fn custom_registration() {
+    let markdown = ContentType::Custom("markdown".into());
+    register_content_type(markdown.clone(), ContentTypeNames {
+        mime: "text/markdown".into(),
+        uti: "net.daringfireball.markdown".into(),
+        windows: "Markdown".into(),
+        x11: "text/markdown".into(),
+    });
+
+    assert_eq!(markdown.name(Namespace::Uti), "net.daringfireball.markdown");
+    assert_eq!(markdown.name(Namespace::Windows), "Markdown");
+    assert_eq!(ContentType::from_name(Namespace::Mime, "text/markdown"), markdown);
+    assert_eq!(
+        ContentType::Custom("net.daringfireball.markdown".into()).normalize(Namespace::Uti),
+        markdown
+    );
+}
@@ -0,0 +1,164 @@
+#![cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    ))
+))]
+
+//! Tests changing the environment of the process.
+//!
+//! They are kept in their own test binary, so they can't affect the tests of the others, and take
+//! the [`Env`] lock so they don't run concurrently.
+
+mod common;
+
+use std::ffi::OsString;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::sync::{Mutex, MutexGuard};
+
+use copypasta::command_clipboard::{CommandClipboardContext, Commands, Selection};
+use copypasta::file_clipboard::FileClipboardContext;
+use copypasta::linux_clipboard::LinuxClipboardContext;
+use copypasta::{get_clipboard_context, ClipboardError, ClipboardProvider};
+
+use common::TempDir;
+
+/// Variables which are changed by the tests.
+const VARS: [&str; 7] =
+    ["PATH", "TMUX", "DISPLAY", "WAYLAND_DISPLAY", "WAYLAND_SOCKET", "XDG_RUNTIME_DIR", "TMPDIR"];
+
+/// Exclusive access to the environment, which is restored on drop.
+struct Env {
+    saved: Vec<(&'static str, Option<OsString>)>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Env {
+    fn lock() -> Self {
+        static LOCK: Mutex<()> = Mutex::new(());
+        let lock = LOCK.lock().unwrap_or_else(|err| err.into_inner());
+        let saved = VARS.iter().map(|var| (*var, std::env::var_os(var))).collect();
+        Env { saved, _lock: lock }
+    }
+}
+
+impl Drop for Env {
+    fn drop(&mut self) {
+        for (var, value) in self.saved.drain(..) {
+            match value {
+                Some(value) => std::env::set_var(var, value),
+                None => std::env::remove_var(var),
+            }
+        }
+    }
+}
+
+#[test]
+fn falls_back_to_nop_without_display() {
+    let _env = Env::lock();
+    std::env::remove_var("WAYLAND_DISPLAY");
+    std::env::remove_var("DISPLAY");
+    std::env::remove_var("TMUX");
+
+    let ctx = get_clipboard_context().unwrap();
+    assert!(matches!(ctx, LinuxClipboardContext::Nop(_)));
+
+    assert!(matches!(ctx.get_contents(), Err(ClipboardError::Unsupported)));
+    assert!(matches!(ctx.get_content_types(), Err(ClipboardError::Unsupported)));
+}
+
+#[test]
+fn tmux_chosen_inside_tmux_without_display() {
+    let _env = Env::lock();
+    std::env::remove_var("WAYLAND_DISPLAY");
+    std::env::remove_var("DISPLAY");
+    std::env::set_var("TMUX", "/tmp/tmux-1000/default,1234,0");
+
+    let ctx = LinuxClipboardContext::new().unwrap();
+    assert!(matches!(ctx, LinuxClipboardContext::Tmux(_)));
+
+    std::env::remove_var("TMUX");
+    let ctx = LinuxClipboardContext::new().unwrap();
+    assert!(matches!(ctx, LinuxClipboardContext::Nop(_)));
+}
+
+#[test]
+fn command_detection() {
+    let _env = Env::lock();
+    let dir = TempDir::new("environment-detection");
+    fs::create_dir_all(&*dir).unwrap();
+    for program in ["wl-copy", "wl-paste", "xclip", "xsel"].iter() {
+        let path = dir.join(program);
+        fs::write(&path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    std::env::set_var("PATH", &*dir);
+
+    // Displays which can't be connected to, so the native backends fail.
+    std::env::set_var("WAYLAND_DISPLAY", "copypasta-nonexistent");
+    std::env::remove_var("DISPLAY");
+    assert_eq!(
+        Commands::detect(Selection::Clipboard),
+        Some(Commands::wl_clipboard(Selection::Clipboard))
+    );
+    assert!(matches!(LinuxClipboardContext::new().unwrap(), LinuxClipboardContext::Command(_)));
+
+    std::env::remove_var("WAYLAND_DISPLAY");
+    std::env::set_var("DISPLAY", ":4242");
+    assert_eq!(Commands::detect(Selection::Primary), Some(Commands::xclip(Selection::Primary)));
+    assert!(matches!(LinuxClipboardContext::new().unwrap(), LinuxClipboardContext::Command(_)));
+
+    fs::remove_file(dir.join("xclip")).unwrap();
+    assert_eq!(Commands::detect(Selection::Clipboard), Some(Commands::xsel(Selection::Clipboard)));
+
+    std::env::remove_var("DISPLAY");
+    assert_eq!(Commands::detect(Selection::Clipboard), None);
+    assert!(matches!(CommandClipboardContext::new(), Err(ClipboardError::Unsupported)));
+}
+
+#[test]
+fn file_default_directory() {
+    let _env = Env::lock();
+    let runtime_dir = TempDir::new("environment-runtime-dir");
+    std::env::set_var("XDG_RUNTIME_DIR", &*runtime_dir);
+
+    let ctx = FileClipboardContext::new().unwrap();
+    assert_eq!(ctx.directory(), runtime_dir.join("copypasta").join("clipboard"));
+
+    ctx.set_contents("default".into()).unwrap();
+    assert!(runtime_dir.join("copypasta/clipboard").is_dir());
+
+    // Without a runtime directory, a private one is created in the temporary directory.
+    let temp_dir = TempDir::new("environment-temp-dir");
+    fs::create_dir_all(&*temp_dir).unwrap();
+    std::env::remove_var("XDG_RUNTIME_DIR");
+    std::env::set_var("TMPDIR", &*temp_dir);
+
+    let ctx = FileClipboardContext::new().unwrap();
+    let root = ctx.directory().parent().unwrap().to_owned();
+    assert_eq!(root.parent().unwrap(), &*temp_dir);
+    assert_eq!(fs::metadata(&root).unwrap().permissions().mode() & 0o777, 0o700);
+
+    // Somebody else could read a directory which is accessible to others.
+    fs::set_permissions(&root, fs::Permissions::from_mode(0o755)).unwrap();
+    assert!(FileClipboardContext::new().is_err());
+}
+
+#[cfg(feature = "wayland")]
+#[test]
+fn wayland_connect_without_compositor() {
+    use copypasta::wayland_clipboard::Clipboard;
+
+    let _env = Env::lock();
+    let runtime_dir = TempDir::new("environment-no-compositor");
+    fs::create_dir_all(&*runtime_dir).unwrap();
+    std::env::set_var("XDG_RUNTIME_DIR", &*runtime_dir);
+    std::env::remove_var("WAYLAND_DISPLAY");
+    std::env::remove_var("WAYLAND_SOCKET");
+
+    assert!(matches!(Clipboard::connect(), Err(ClipboardError::Backend(_))));
+}
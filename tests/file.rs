@@ -0,0 +1,125 @@
+#![cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    ))
+))]
+
+mod common;
+
+use std::collections::HashMap;
+use std::thread;
+
+use copypasta::file_clipboard::{FileClipboardContext, Selection};
+use copypasta::{ClipboardError, ClipboardProvider, ContentType};
+
+use common::TempDir;
+
+#[test]
+fn set_and_get() {
+    let dir = TempDir::new("file-set-and-get");
+    let ctx = FileClipboardContext::with_directory(&*dir);
+
+    assert!(matches!(ctx.get_contents(), Err(ClipboardError::Empty)));
+    assert!(ctx.get_content_types().unwrap().is_empty());
+
+    ctx.set_contents("ünïcödé".into()).unwrap();
+    assert_eq!(ctx.get_contents().unwrap(), "ünïcödé");
+    assert_eq!(ctx.get_content_types().unwrap(), [ContentType::Text]);
+
+    ctx.clear().unwrap();
+    assert!(matches!(ctx.get_contents(), Err(ClipboardError::Empty)));
+}
+
+#[test]
+fn contexts_share_directory() {
+    let dir = TempDir::new("file-share");
+    let first = FileClipboardContext::with_directory(&*dir);
+    let second = FileClipboardContext::with_directory(&*dir);
+
+    first.set_contents("shared".into()).unwrap();
+    assert_eq!(second.get_contents().unwrap(), "shared");
+}
+
+#[test]
+fn selections_are_independent() {
+    let dir = TempDir::new("file-selections");
+    let clipboard = FileClipboardContext::with_directory(&*dir);
+    let primary = FileClipboardContext::with_directory(&*dir).with_selection(Selection::Primary);
+
+    clipboard.set_contents("clipboard".into()).unwrap();
+    primary.set_contents("primary".into()).unwrap();
+
+    assert_eq!(clipboard.get_contents().unwrap(), "clipboard");
+    assert_eq!(primary.get_contents().unwrap(), "primary");
+    assert_eq!(primary.directory(), dir.join("primary"));
+}
+
+#[test]
+fn multiple_content_types() {
+    let dir = TempDir::new("file-multiple");
+    let ctx = FileClipboardContext::with_directory(&*dir);
+
+    let custom = ContentType::Custom("application/x-copypasta;v=1".into());
+    let mut map = HashMap::new();
+    map.insert(ContentType::Html, b"<b>bold</b>".to_vec());
+    map.insert(ContentType::Text, b"bold".to_vec());
+    map.insert(custom.clone(), vec![0, 1, 2, 255]);
+    ctx.set_content_types(map).unwrap();
+
+    let cts = ctx.get_content_types().unwrap();
+    assert_eq!(cts.len(), 3);
+    assert!(cts.contains(&ContentType::Html));
+    assert!(cts.contains(&ContentType::Text));
+    assert!(cts.contains(&custom));
+
+    assert_eq!(ctx.get_content_for_type(&custom).unwrap(), [0, 1, 2, 255]);
+    assert_eq!(ctx.get_contents().unwrap(), "bold");
+
+    let result = ctx.get_content_for_type(&ContentType::Png);
+    assert!(matches!(result, Err(ClipboardError::FormatNotAvailable)));
+
+    // Setting the contents replaces all content types.
+    ctx.set_contents("plain".into()).unwrap();
+    assert_eq!(ctx.get_content_types().unwrap(), [ContentType::Text]);
+}
+
+#[test]
+fn concurrent_writers() {
+    let dir = TempDir::new("file-concurrent");
+
+    let writers: Vec<_> = (0..4)
+        .map(|i| {
+            let ctx = FileClipboardContext::with_directory(&*dir);
+            thread::spawn(move || {
+                for _ in 0..50 {
+                    let mut map = HashMap::new();
+                    map.insert(ContentType::Text, i.to_string().into_bytes());
+                    map.insert(ContentType::Html, format!("<p>{}</p>", i).into_bytes());
+                    ctx.set_content_types(map).unwrap();
+                }
+            })
+        })
+        .collect();
+
+    // Writes replace the files under a lock, so readers never see a partially written value.
+    let reader = FileClipboardContext::with_directory(&*dir);
+    for _ in 0..50 {
+        match reader.get_contents() {
+            Ok(text) => assert!(text.parse::<u8>().unwrap() < 4),
+            Err(ClipboardError::Empty) => (),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    let text = reader.get_contents().unwrap();
+    let html = String::from_utf8(reader.get_content_for_type(&ContentType::Html).unwrap());
+    assert_eq!(html.unwrap(), format!("<p>{}</p>", text));
+}
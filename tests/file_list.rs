@@ -0,0 +1,91 @@
+#![cfg(unix)]
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use copypasta::memory_clipboard::InMemoryClipboardContext;
+use copypasta::{ClipboardError, ClipboardProvider, ContentType, FileOperation};
+
+fn gnome_copied_files() -> ContentType {
+    ContentType::Custom("x-special/gnome-copied-files".into())
+}
+
+#[test]
+fn set_and_get() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+    let paths = vec![PathBuf::from("/tmp/a file.txt"), PathBuf::from("/tmp/ünïcödé/100%")];
+
+    ctx.set_file_list(&paths).unwrap();
+    assert_eq!(ctx.get_file_list().unwrap(), paths);
+    assert_eq!(ctx.get_file_operation().unwrap(), FileOperation::Copy);
+}
+
+#[test]
+fn percent_encoding() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+    let paths = [PathBuf::from("/tmp/a file.txt"), PathBuf::from("/tmp/ü#?")];
+
+    ctx.set_file_list(&paths).unwrap();
+    assert_eq!(
+        ctx.get_content_for_type(&ContentType::Url).unwrap(),
+        b"file:///tmp/a%20file.txt\r\nfile:///tmp/%C3%BC%23%3F\r\n",
+    );
+    assert_eq!(
+        ctx.get_content_for_type(&gnome_copied_files()).unwrap(),
+        b"copy\nfile:///tmp/a%20file.txt\nfile:///tmp/%C3%BC%23%3F",
+    );
+    assert_eq!(ctx.get_contents().unwrap(), "/tmp/a file.txt\n/tmp/ü#?");
+}
+
+#[test]
+fn cut() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+    let paths = [PathBuf::from("/tmp/moved")];
+
+    ctx.set_file_list_with_operation(&paths, FileOperation::Cut).unwrap();
+    assert_eq!(ctx.get_file_operation().unwrap(), FileOperation::Cut);
+    assert_eq!(ctx.get_content_for_type(&gnome_copied_files()).unwrap(), b"cut\nfile:///tmp/moved");
+}
+
+#[test]
+fn relative_paths() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+
+    ctx.set_file_list(&[PathBuf::from("relative")]).unwrap();
+    assert_eq!(ctx.get_file_list().unwrap(), [std::env::current_dir().unwrap().join("relative")]);
+}
+
+#[test]
+fn parse_uri_list() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+
+    let uri_list = "# comment\r\nfile:///a%2Fb\r\nfile://localhost/c\r\nhttps://example.org/d\r\n\
+                    file://remote/e\r\nfile:///bad%2\r\n";
+    let mut map = HashMap::new();
+    map.insert(ContentType::Url, uri_list.as_bytes().to_vec());
+    ctx.set_content_types(map).unwrap();
+
+    assert_eq!(ctx.get_file_list().unwrap(), [PathBuf::from("/a/b"), PathBuf::from("/c")]);
+    assert_eq!(ctx.get_file_operation().unwrap(), FileOperation::Copy);
+}
+
+#[test]
+fn gnome_copied_files_only() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+
+    let mut map = HashMap::new();
+    map.insert(gnome_copied_files(), b"cut\nfile:///x\nfile:///y%20z".to_vec());
+    ctx.set_content_types(map).unwrap();
+
+    assert_eq!(ctx.get_file_list().unwrap(), [PathBuf::from("/x"), PathBuf::from("/y z")]);
+    assert_eq!(ctx.get_file_operation().unwrap(), FileOperation::Cut);
+}
+
+#[test]
+fn no_files() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+    ctx.set_contents("/not/a/file/list".into()).unwrap();
+
+    assert!(matches!(ctx.get_file_list(), Err(ClipboardError::FormatNotAvailable)));
+    assert!(matches!(ctx.get_file_operation(), Err(ClipboardError::FormatNotAvailable)));
+}
@@ -0,0 +1,63 @@
+use copypasta::memory_clipboard::InMemoryClipboardContext;
+use copypasta::{ClipboardError, ClipboardProvider, ContentType};
+
+fn fallback(html: &str) -> String {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+    ctx.set_html(html, None).unwrap();
+    ctx.get_contents().unwrap()
+}
+
+#[test]
+fn set_and_get() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+
+    ctx.set_html("<b>bold</b>", Some("*bold*")).unwrap();
+    assert_eq!(ctx.get_html().unwrap(), "<b>bold</b>");
+    assert_eq!(ctx.get_contents().unwrap(), "*bold*");
+
+    let mut cts = ctx.get_content_types().unwrap();
+    cts.sort_by_key(|ct| format!("{:?}", ct));
+    assert_eq!(cts, [ContentType::Html, ContentType::Text]);
+}
+
+#[test]
+fn no_html() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+    ctx.set_contents("plain".into()).unwrap();
+
+    assert!(matches!(ctx.get_html(), Err(ClipboardError::FormatNotAvailable)));
+}
+
+#[test]
+fn strip_tags() {
+    assert_eq!(fallback("<b>bold</b> and <i>italic</i>"), "bold and italic");
+    assert_eq!(fallback("<a href=\"https://example.org\">link</a>"), "link");
+    assert_eq!(fallback("  lots \n of\t whitespace  "), "lots of whitespace");
+    assert_eq!(fallback("<p>first</p><p>second</p>"), "first\nsecond");
+    assert_eq!(fallback("line<br>break<BR/>again"), "line\nbreak\nagain");
+    assert_eq!(fallback("<ul><li>one</li> <li>two</li></ul>"), "one\ntwo");
+}
+
+#[test]
+fn literal_angle_brackets() {
+    assert_eq!(fallback("a < b and c > d"), "a < b and c > d");
+    assert_eq!(fallback("<3 <b>you</b>"), "<3 you");
+    assert_eq!(fallback("1 <2 and 2> 1"), "1 <2 and 2> 1");
+    assert_eq!(fallback("unclosed <b"), "unclosed <b");
+    assert_eq!(fallback("<i>trailing</i> <"), "trailing <");
+}
+
+#[test]
+fn hidden_content() {
+    let html = "<html><head><title>Title</title><style>b { color: red }</style></head><body><!-- \
+                <b>comment</b> --><SCRIPT>alert('<b>')</SCRIPT>visible</body></html>";
+    assert_eq!(fallback(html), "visible");
+}
+
+#[test]
+fn entities() {
+    assert_eq!(fallback("a &lt;b&gt; &amp;&amp; &quot;c&quot;"), "a <b> && \"c\"");
+    assert_eq!(fallback("&#65;&#x42;&#X43;"), "ABC");
+    assert_eq!(fallback("non&nbsp;breaking"), "non\u{a0}breaking");
+    assert_eq!(fallback("&unknown; & alone"), "&unknown; & alone");
+}
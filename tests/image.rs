@@ -0,0 +1,127 @@
+#![cfg(feature = "image-data")]
+
+use std::collections::HashMap;
+
+use copypasta::memory_clipboard::InMemoryClipboardContext;
+use copypasta::{ClipboardError, ClipboardProvider, ContentType, ImageData, Result};
+
+/// 2x1 image with an opaque red and a translucent blue pixel.
+fn image() -> ImageData {
+    ImageData { width: 2, height: 1, rgba: vec![255, 0, 0, 255, 0, 0, 255, 128] }
+}
+
+#[test]
+fn set_and_get() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+
+    ctx.set_image(image()).unwrap();
+    assert_eq!(ctx.get_content_types().unwrap(), [ContentType::Png]);
+    assert_eq!(ctx.get_image().unwrap(), image());
+}
+
+#[test]
+fn decode_bmp() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+
+    // 1x1 24-bit BMP with a single green pixel.
+    let mut bmp = b"BM".to_vec();
+    bmp.extend_from_slice(&58u32.to_le_bytes());
+    bmp.extend_from_slice(&[0, 0, 0, 0]);
+    bmp.extend_from_slice(&54u32.to_le_bytes());
+    bmp.extend_from_slice(&40u32.to_le_bytes());
+    bmp.extend_from_slice(&1i32.to_le_bytes());
+    bmp.extend_from_slice(&1i32.to_le_bytes());
+    bmp.extend_from_slice(&1u16.to_le_bytes());
+    bmp.extend_from_slice(&24u16.to_le_bytes());
+    bmp.extend_from_slice(&[0; 24]);
+    bmp.extend_from_slice(&[0, 255, 0, 0]);
+
+    let mut map = HashMap::new();
+    map.insert(ContentType::Custom("image/bmp".into()), bmp);
+    map.insert(ContentType::Text, b"not an image".to_vec());
+    ctx.set_content_types(map).unwrap();
+
+    let image = ctx.get_image().unwrap();
+    assert_eq!((image.width, image.height), (1, 1));
+    assert_eq!(image.rgba, [0, 255, 0, 255]);
+}
+
+#[test]
+fn prefer_png() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+    ctx.set_image(image()).unwrap();
+    let png = ctx.get_content_for_type(&ContentType::Png).unwrap();
+
+    let mut map = HashMap::new();
+    map.insert(ContentType::Custom("image/jpeg".into()), b"broken".to_vec());
+    map.insert(ContentType::Png, png);
+    ctx.set_content_types(map).unwrap();
+
+    assert_eq!(ctx.get_image().unwrap(), image());
+}
+
+/// In-memory clipboard of a platform whose applications prefer BMP images.
+struct BmpClipboard(InMemoryClipboardContext);
+
+impl ClipboardProvider for BmpClipboard {
+    fn get_contents(&self) -> Result<String> {
+        self.0.get_contents()
+    }
+
+    fn set_contents(&self, data: String) -> Result<()> {
+        self.0.set_contents(data)
+    }
+
+    fn get_content_types(&self) -> Result<Vec<ContentType>> {
+        self.0.get_content_types()
+    }
+
+    fn get_content_for_type(&self, ct: &ContentType) -> Result<Vec<u8>> {
+        self.0.get_content_for_type(ct)
+    }
+
+    fn set_content_types(&self, map: HashMap<ContentType, Vec<u8>>) -> Result<()> {
+        self.0.set_content_types(map)
+    }
+
+    fn image_content_types(&self) -> Vec<ContentType> {
+        vec![ContentType::Custom("image/bmp".into())]
+    }
+}
+
+#[test]
+fn preferred_format() {
+    let ctx = BmpClipboard(InMemoryClipboardContext::new().unwrap());
+
+    ctx.set_image(image()).unwrap();
+    assert_eq!(ctx.get_content_types().unwrap(), [ContentType::Custom("image/bmp".into())]);
+    assert_eq!(ctx.get_image().unwrap(), image());
+}
+
+#[test]
+fn no_image() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+    ctx.set_contents("text".into()).unwrap();
+
+    assert!(matches!(ctx.get_image(), Err(ClipboardError::FormatNotAvailable)));
+}
+
+#[test]
+fn invalid_image() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+
+    let mut map = HashMap::new();
+    map.insert(ContentType::Png, b"not a png".to_vec());
+    ctx.set_content_types(map).unwrap();
+    assert!(matches!(ctx.get_image(), Err(ClipboardError::Backend(_))));
+
+    let truncated = ImageData { rgba: vec![0; 7], ..image() };
+    assert!(matches!(ctx.set_image(truncated), Err(ClipboardError::Backend(_))));
+
+    // An empty image can still be too wide to encode.
+    #[cfg(target_pointer_width = "64")]
+    {
+        let too_wide = ImageData { width: 1 << 32, height: 0, rgba: Vec::new() };
+        assert!(matches!(ctx.set_image(too_wide), Err(ClipboardError::Backend(_))));
+    }
+}
@@ -0,0 +1,39 @@
+#![cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    ))
+))]
+
+#[cfg(all(feature = "x11", feature = "wayland"))]
+#[test]
+fn content_type_normalization() {
+    use copypasta::linux_clipboard::LinuxClipboardContext;
+    use copypasta::{register_content_type, ClipboardProvider, ContentType, ContentTypeNames};
+
+    type Context = LinuxClipboardContext;
+
+    let normalize = |name: &str| Context::normalize_content_type(ContentType::Custom(name.into()));
+    assert_eq!(normalize("UTF8_STRING"), ContentType::Text);
+    assert_eq!(normalize("text/plain"), ContentType::Text);
+    assert_eq!(normalize("text/html"), ContentType::Html);
+    assert_eq!(normalize("image/bmp"), ContentType::Custom("image/bmp".into()));
+
+    assert_eq!(Context::denormalize_content_type(ContentType::Text), "text/plain;charset=utf-8");
+    assert_eq!(Context::denormalize_content_type(ContentType::Png), "image/png");
+
+    // Names of either backend are recognized, whichever one was picked.
+    let markdown = ContentType::Custom("markdown".into());
+    register_content_type(markdown.clone(), ContentTypeNames {
+        mime: "text/markdown".into(),
+        uti: "net.daringfireball.markdown".into(),
+        windows: "Markdown".into(),
+        x11: "MARKDOWN".into(),
+    });
+    assert_eq!(normalize("MARKDOWN"), markdown);
+    assert_eq!(normalize("text/markdown"), markdown);
+    assert_eq!(Context::denormalize_content_type(markdown), "text/markdown");
+}
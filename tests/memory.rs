@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use copypasta::memory_clipboard::{InMemoryClipboardContext, Selection};
+use copypasta::{ClipboardError, ClipboardEvent, ClipboardProvider, ContentType};
+
+#[test]
+fn set_and_get() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+    assert!(matches!(ctx.get_contents(), Err(ClipboardError::Empty)));
+
+    ctx.set_contents("Hello, world!".into()).unwrap();
+    assert_eq!(ctx.get_contents().unwrap(), "Hello, world!");
+    assert_eq!(ctx.get_content_types().unwrap(), [ContentType::Text]);
+
+    ctx.clear();
+    assert!(matches!(ctx.get_contents(), Err(ClipboardError::Empty)));
+}
+
+#[test]
+fn clones_share_contents() {
+    let first = InMemoryClipboardContext::new().unwrap();
+    let second = first.clone();
+
+    first.set_contents("shared".into()).unwrap();
+    assert_eq!(second.get_contents().unwrap(), "shared");
+
+    // Separate contexts don't share anything.
+    let other = InMemoryClipboardContext::new().unwrap();
+    assert!(matches!(other.get_contents(), Err(ClipboardError::Empty)));
+}
+
+#[test]
+fn selections_are_independent() {
+    let clipboard = InMemoryClipboardContext::new().unwrap();
+    let primary = clipboard.with_selection(Selection::Primary);
+    assert_eq!(primary.selection(), Selection::Primary);
+
+    clipboard.set_contents("clipboard".into()).unwrap();
+    primary.set_contents("primary".into()).unwrap();
+
+    assert_eq!(clipboard.get_contents().unwrap(), "clipboard");
+    assert_eq!(primary.get_contents().unwrap(), "primary");
+    assert_eq!(primary.with_selection(Selection::Clipboard).get_contents().unwrap(), "clipboard");
+}
+
+#[test]
+fn multiple_content_types() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+
+    let mut map = HashMap::new();
+    map.insert(ContentType::Html, b"<b>bold</b>".to_vec());
+    map.insert(ContentType::Custom("image/png".into()), vec![0, 1, 2]);
+    ctx.set_content_types(map).unwrap();
+
+    let cts = ctx.get_content_types().unwrap();
+    assert_eq!(cts.len(), 2);
+    assert!(cts.contains(&ContentType::Html));
+    assert!(cts.contains(&ContentType::Png));
+
+    assert_eq!(ctx.get_content_for_type(&ContentType::Html).unwrap(), b"<b>bold</b>");
+    assert_eq!(ctx.get_content_for_type(&ContentType::Png).unwrap(), [0, 1, 2]);
+    let custom_html = ContentType::Custom("text/html".into());
+    assert_eq!(ctx.get_content_for_type(&custom_html).unwrap(), b"<b>bold</b>");
+    assert!(matches!(ctx.get_contents(), Err(ClipboardError::FormatNotAvailable)));
+}
+
+#[test]
+fn invalid_utf8() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+
+    let mut map = HashMap::new();
+    map.insert(ContentType::Text, vec![0xff, 0xfe]);
+    ctx.set_content_types(map).unwrap();
+
+    assert!(matches!(ctx.get_contents(), Err(ClipboardError::InvalidUtf8)));
+}
+
+#[test]
+fn watch() {
+    let clipboard = InMemoryClipboardContext::new().unwrap();
+    let primary = clipboard.with_selection(Selection::Primary);
+    let mut watch = clipboard.watch().unwrap();
+    assert_eq!(watch.try_next(), None);
+
+    clipboard.clone().set_contents("first".into()).unwrap();
+    primary.set_contents("ignored".into()).unwrap();
+    clipboard.clear();
+
+    assert_eq!(watch.next(), Some(ClipboardEvent::Changed));
+    assert_eq!(watch.next(), Some(ClipboardEvent::Cleared));
+    assert_eq!(watch.next_timeout(Duration::from_millis(10)), None);
+}
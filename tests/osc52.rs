@@ -0,0 +1,137 @@
+#![cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    ))
+))]
+
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::thread;
+use std::time::Duration;
+
+use copypasta::osc52_clipboard::{Osc52ClipboardContext, Passthrough, Selection};
+use copypasta::{ClipboardError, ClipboardProvider};
+
+/// Open a pty, returning the terminal emulator's end and a context using the other end.
+fn pty() -> (File, Osc52ClipboardContext) {
+    unsafe {
+        let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        assert!(master >= 0);
+        assert_eq!(libc::grantpt(master), 0);
+        assert_eq!(libc::unlockpt(master), 0);
+        let name = CStr::from_ptr(libc::ptsname(master)).to_str().unwrap().to_owned();
+
+        let terminal = OpenOptions::new().read(true).write(true).open(name).unwrap();
+        let ctx =
+            Osc52ClipboardContext::with_terminal(terminal).with_passthrough(Passthrough::None);
+        (File::from_raw_fd(master), ctx)
+    }
+}
+
+/// Read `len` bytes written to the terminal.
+fn read_output(master: &mut File, len: usize) -> String {
+    let mut output = vec![0; len];
+    master.read_exact(&mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn set() {
+    let (mut master, ctx) = pty();
+
+    ctx.set_contents("Hello, world!".into()).unwrap();
+    let expected = "\x1b]52;c;SGVsbG8sIHdvcmxkIQ==\x07";
+    assert_eq!(read_output(&mut master, expected.len()), expected);
+
+    ctx.set_contents("ünï".into()).unwrap();
+    let expected = "\x1b]52;c;w7xuw68=\x07";
+    assert_eq!(read_output(&mut master, expected.len()), expected);
+}
+
+#[test]
+fn set_primary() {
+    let (mut master, ctx) = pty();
+    let ctx = ctx.with_selection(Selection::Primary);
+
+    ctx.set_contents("ab".into()).unwrap();
+    let expected = "\x1b]52;p;YWI=\x07";
+    assert_eq!(read_output(&mut master, expected.len()), expected);
+}
+
+#[test]
+fn tmux_passthrough() {
+    let (mut master, ctx) = pty();
+    let ctx = ctx.with_passthrough(Passthrough::Tmux);
+
+    ctx.set_contents("a".into()).unwrap();
+    let expected = "\x1bPtmux;\x1b\x1b]52;c;YQ==\x07\x1b\\";
+    assert_eq!(read_output(&mut master, expected.len()), expected);
+}
+
+#[test]
+fn screen_passthrough() {
+    let (mut master, ctx) = pty();
+    let ctx = ctx.with_passthrough(Passthrough::Screen);
+
+    let text = "x".repeat(60);
+    ctx.set_contents(text).unwrap();
+    let sequence = format!("\x1b]52;c;{}\x07", "eHh4".repeat(20));
+    let expected = format!("\x1bP{}\x1b\\\x1bP{}\x1b\\", &sequence[..76], &sequence[76..]);
+    assert_eq!(read_output(&mut master, expected.len()), expected);
+}
+
+#[test]
+fn get() {
+    let (mut master, ctx) = pty();
+
+    let terminal = thread::spawn(move || {
+        let query = "\x1b]52;c;?\x07";
+        assert_eq!(read_output(&mut master, query.len()), query);
+
+        // ST terminates the answer as well, even if its bytes arrive separately.
+        master.write_all(b"typed\x1b]52;c;d29ybGQ=\x1b").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        master.write_all(b"\\").unwrap();
+
+        assert_eq!(read_output(&mut master, query.len()), query);
+        master.write_all(b"\x1b]52;c;YQ==\x07more").unwrap();
+        master
+    });
+
+    assert_eq!(ctx.get_contents().unwrap(), "world");
+    assert_eq!(ctx.get_contents().unwrap(), "a");
+    terminal.join().unwrap();
+
+    // Input around the answers is kept, without the end of the terminator.
+    assert_eq!(ctx.take_input(), b"typedmore");
+    assert!(ctx.take_input().is_empty());
+}
+
+#[test]
+fn get_empty() {
+    let (mut master, ctx) = pty();
+
+    let terminal = thread::spawn(move || {
+        let query = "\x1b]52;c;?\x07";
+        assert_eq!(read_output(&mut master, query.len()), query);
+        master.write_all(b"\x1b]52;c;\x07").unwrap();
+        master
+    });
+
+    assert!(matches!(ctx.get_contents(), Err(ClipboardError::Empty)));
+    terminal.join().unwrap();
+}
+
+#[test]
+fn get_timeout() {
+    let (_master, ctx) = pty();
+    let ctx = ctx.with_timeout(Duration::from_millis(100));
+
+    assert!(matches!(ctx.get_contents(), Err(ClipboardError::Timeout)));
+}
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+use std::thread;
+
+use copypasta::memory_clipboard::InMemoryClipboardContext;
+use copypasta::nop_clipboard::NopClipboardContext;
+use copypasta::{ClipboardContext, ClipboardProvider};
+
+/// Fails to compile unless the provider can be shared between threads.
+fn assert_shareable<T: ClipboardProvider + Send + Sync + 'static>() {}
+
+#[test]
+fn providers_are_shareable() {
+    assert_shareable::<ClipboardContext>();
+    assert_shareable::<InMemoryClipboardContext>();
+    assert_shareable::<NopClipboardContext>();
+}
+
+#[cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    ))
+))]
+#[test]
+fn linux_providers_are_shareable() {
+    assert_shareable::<copypasta::linux_clipboard::LinuxClipboardContext>();
+
+    #[cfg(feature = "x11")]
+    {
+        use copypasta::x11rb_clipboard::{Clipboard, Primary, Secondary, X11RbClipboardContext};
+        assert_shareable::<X11RbClipboardContext<Clipboard>>();
+        assert_shareable::<X11RbClipboardContext<Primary>>();
+        assert_shareable::<X11RbClipboardContext<Secondary>>();
+    }
+
+    #[cfg(feature = "wayland")]
+    {
+        assert_shareable::<copypasta::wayland_clipboard::Clipboard>();
+        assert_shareable::<copypasta::wayland_clipboard::Primary>();
+    }
+}
+
+#[test]
+fn share_trait_object() {
+    let ctx: Arc<dyn ClipboardProvider> = Arc::new(InMemoryClipboardContext::new().unwrap());
+
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            let ctx = ctx.clone();
+            thread::spawn(move || ctx.set_contents(i.to_string()).unwrap())
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let contents = ctx.get_contents().unwrap();
+    assert!(["0", "1", "2", "3"].contains(&contents.as_str()));
+}
@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use copypasta::memory_clipboard::InMemoryClipboardContext;
+use copypasta::{ClipboardError, ClipboardProvider, ContentType};
+
+#[test]
+fn set_text() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+
+    ctx.set_text("borrowed").unwrap();
+    assert_eq!(ctx.get_contents().unwrap(), "borrowed");
+
+    // Trait objects can set text too.
+    let shared: Arc<dyn ClipboardProvider> = Arc::new(ctx.clone());
+    shared.set_text("shared").unwrap();
+    assert_eq!(ctx.get_contents().unwrap(), "shared");
+}
+
+#[test]
+fn lossy() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+
+    let mut map = HashMap::new();
+    map.insert(ContentType::Text, b"caf\xe9 ok".to_vec());
+    ctx.set_content_types(map).unwrap();
+
+    assert!(matches!(ctx.get_contents(), Err(ClipboardError::InvalidUtf8)));
+    assert_eq!(ctx.get_contents_lossy().unwrap(), "caf\u{fffd} ok");
+}
+
+#[test]
+fn lossy_valid_text() {
+    let ctx = InMemoryClipboardContext::new().unwrap();
+
+    assert!(matches!(ctx.get_contents_lossy(), Err(ClipboardError::Empty)));
+
+    ctx.set_text("valid").unwrap();
+    assert_eq!(ctx.get_contents_lossy().unwrap(), "valid");
+}
@@ -0,0 +1,98 @@
+#![cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    ))
+))]
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Once;
+
+use copypasta::tmux_clipboard::TmuxClipboardContext;
+use copypasta::{ClipboardError, ClipboardProvider};
+
+/// Stand-in for `tmux`, storing the buffers next to the script.
+const TMUX: &str = r#"#!/bin/sh
+PATH=/bin:/usr/bin
+dir=$(dirname "$0"); command=$1; buffer=
+shift
+while [ $# -gt 0 ]; do
+    case "$1" in
+        -b) buffer=$2; shift ;;
+    esac
+    shift
+done
+case $command in
+    load-buffer)
+        if [ -z "$buffer" ]; then
+            buffer=buffer$(ls "$dir/buffers" | wc -l | tr -d ' ')
+            echo "$buffer" > "$dir/latest"
+        fi
+        cat > "$dir/buffers/$buffer"
+        ;;
+    save-buffer)
+        if [ -z "$buffer" ]; then
+            [ -f "$dir/latest" ] || { echo "no buffers" >&2; exit 1; }
+            buffer=$(cat "$dir/latest")
+        fi
+        [ -f "$dir/buffers/$buffer" ] || { echo "no buffer $buffer" >&2; exit 1; }
+        cat "$dir/buffers/$buffer"
+        ;;
+    *) echo "unknown command $command" >&2; exit 1 ;;
+esac
+"#;
+
+/// Put the stub first on the `PATH`.
+///
+/// The stub is kept in the scratch directory of cargo, since it's needed until the last test ends.
+fn setup() {
+    static SETUP: Once = Once::new();
+    SETUP.call_once(|| {
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("tmux");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("buffers")).unwrap();
+
+        let path = dir.join("tmux");
+        fs::write(&path, TMUX).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let mut paths = vec![dir];
+        paths.extend(std::env::var_os("PATH").iter().flat_map(std::env::split_paths));
+        std::env::set_var("PATH", std::env::join_paths(paths).unwrap());
+    });
+}
+
+#[test]
+fn most_recent_buffer() {
+    setup();
+    let ctx = TmuxClipboardContext::new().unwrap();
+    assert_eq!(ctx.buffer(), None);
+
+    assert!(matches!(ctx.get_contents(), Err(ClipboardError::Empty)));
+
+    ctx.set_contents("first".into()).unwrap();
+    ctx.set_contents("second".into()).unwrap();
+    assert_eq!(ctx.get_contents().unwrap(), "second");
+}
+
+#[test]
+fn named_buffer() {
+    setup();
+    let ctx = TmuxClipboardContext::with_buffer("copypasta");
+    assert_eq!(ctx.buffer(), Some("copypasta"));
+
+    assert!(matches!(ctx.get_contents(), Err(ClipboardError::Empty)));
+
+    // Data goes through unchanged, including trailing newlines.
+    ctx.set_contents("multiple\nlines\n\n".into()).unwrap();
+    assert_eq!(ctx.get_contents().unwrap(), "multiple\nlines\n\n");
+
+    let other = TmuxClipboardContext::with_buffer("other");
+    other.set_contents("other".into()).unwrap();
+    assert_eq!(ctx.get_contents().unwrap(), "multiple\nlines\n\n");
+}
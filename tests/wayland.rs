@@ -0,0 +1,222 @@
+#![cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    )),
+    feature = "wayland"
+))]
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::future::Future;
+use std::io::Write;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use copypasta::wayland_clipboard::{Clipboard, Primary};
+use copypasta::{ClipboardError, ClipboardProvider, ContentType};
+
+/// All tests share the same selections and environment, so they must not run concurrently.
+static COMPOSITOR_LOCK: Mutex<()> = Mutex::new(());
+
+fn lock() -> MutexGuard<'static, ()> {
+    COMPOSITOR_LOCK.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Get exclusive access to the compositor, and the clipboards of a new connection to it.
+///
+/// Returns `None` without a compositor supporting wlr data control to talk to, like sway with
+/// `WLR_BACKENDS=headless`.
+fn connect() -> Option<(MutexGuard<'static, ()>, Primary, Clipboard)> {
+    std::env::var_os("WAYLAND_DISPLAY")?;
+    let guard = lock();
+    match Clipboard::connect() {
+        Ok((primary, clipboard)) => Some((guard, primary, clipboard)),
+        Err(ClipboardError::Unsupported) => None,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+#[test]
+fn content_type_normalization() {
+    let normalize =
+        |name: &str| Clipboard::normalize_content_type(ContentType::Custom(name.into()));
+    assert_eq!(normalize("text/plain;charset=utf-8"), ContentType::Text);
+    assert_eq!(normalize("UTF8_STRING"), ContentType::Text);
+    assert_eq!(normalize("text/plain"), ContentType::Text);
+    assert_eq!(normalize("text/html"), ContentType::Html);
+    assert_eq!(normalize("image/png"), ContentType::Png);
+    assert_eq!(normalize("text/rtf"), ContentType::Rtf);
+    assert_eq!(normalize("application/pdf"), ContentType::Pdf);
+    assert_eq!(normalize("text/uri-list"), ContentType::Url);
+    assert_eq!(normalize("image/bmp"), ContentType::Custom("image/bmp".into()));
+
+    assert_eq!(Clipboard::denormalize_content_type(ContentType::Text), "text/plain;charset=utf-8");
+    assert_eq!(Primary::denormalize_content_type(ContentType::Png), "image/png");
+    assert_eq!(Clipboard::denormalize_content_type(ContentType::Custom("foo".into())), "foo");
+}
+
+#[test]
+fn set_and_get_from_another_connection() {
+    let (_lock, _, owner) = match connect() {
+        Some(connection) => connection,
+        None => return,
+    };
+    let (_, reader) = Clipboard::connect().unwrap();
+
+    owner.set_contents("ünïcödé".into()).unwrap();
+    assert_eq!(reader.get_contents().unwrap(), "ünïcödé");
+    assert_eq!(owner.get_contents().unwrap(), "ünïcödé");
+}
+
+#[test]
+fn selections_are_independent() {
+    let (_lock, primary, clipboard) = match connect() {
+        Some(connection) => connection,
+        None => return,
+    };
+
+    clipboard.set_contents("clipboard".into()).unwrap();
+    match primary.set_contents("primary".into()) {
+        // The compositor only implements the first version of data control.
+        Err(ClipboardError::Unsupported) => return,
+        result => result.unwrap(),
+    }
+
+    assert_eq!(primary.get_contents().unwrap(), "primary");
+    assert_eq!(clipboard.get_contents().unwrap(), "clipboard");
+}
+
+#[test]
+fn multiple_content_types() {
+    let (_lock, _, owner) = match connect() {
+        Some(connection) => connection,
+        None => return,
+    };
+    let (_, reader) = Clipboard::connect().unwrap();
+
+    let custom = ContentType::Custom("application/x-copypasta-test".into());
+    let mut map = HashMap::new();
+    map.insert(ContentType::Html, b"<b>bold</b>".to_vec());
+    map.insert(ContentType::Text, b"bold".to_vec());
+    map.insert(custom.clone(), vec![0, 1, 2, 255]);
+    owner.set_content_types(map).unwrap();
+
+    let cts = reader.get_content_types().unwrap();
+    assert!(cts.contains(&ContentType::Html));
+    assert!(cts.contains(&ContentType::Text));
+    assert!(cts.contains(&custom));
+
+    assert_eq!(reader.get_content_for_type(&ContentType::Html).unwrap(), b"<b>bold</b>");
+    assert_eq!(reader.get_content_for_type(&custom).unwrap(), [0, 1, 2, 255]);
+    assert_eq!(reader.get_contents().unwrap(), "bold");
+
+    let result = reader.get_content_for_type(&ContentType::Png);
+    assert!(matches!(result, Err(ClipboardError::FormatNotAvailable)));
+}
+
+#[test]
+fn owner_never_closing_the_pipe() {
+    let (_lock, _, reader) = match connect() {
+        Some(connection) => connection,
+        None => return,
+    };
+    reader.set_timeout(Duration::from_millis(200));
+
+    // The owner stays around until the end of the tests, since it blocks on the display.
+    let (ready, owner_ready) = mpsc::channel();
+    thread::spawn(move || hanging_owner(ready, mpsc::channel().0));
+    owner_ready.recv().unwrap();
+
+    assert!(matches!(reader.get_contents(), Err(ClipboardError::Timeout)));
+}
+
+#[test]
+fn cancelled_read_closes_the_pipe() {
+    let (_lock, _, reader) = match connect() {
+        Some(connection) => connection,
+        None => return,
+    };
+
+    let (ready, owner_ready) = mpsc::channel();
+    let (pipes, owner_pipes) = mpsc::channel();
+    thread::spawn(move || hanging_owner(ready, pipes));
+    owner_ready.recv().unwrap();
+
+    // The request is sent when the future is first polled.
+    let mut read = copypasta::AsyncClipboardProvider::get_contents(&reader);
+    let waker = Waker::from(Arc::new(NoopWaker));
+    assert!(Pin::new(&mut read).poll(&mut Context::from_waker(&waker)).is_pending());
+    let mut pipe = unsafe { File::from_raw_fd(owner_pipes.recv().unwrap()) };
+
+    // Dropping the read closes the pipe, long before the read would time out.
+    drop(read);
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while pipe.write_all(b"late").is_ok() {
+        assert!(Instant::now() < deadline, "pipe still open");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Own the clipboard through data control, without ever writing to or closing the pipes of the
+/// clients pasting from it, which are sent to `pipes` instead.
+fn hanging_owner(ready: mpsc::Sender<()>, pipes: mpsc::Sender<RawFd>) {
+    use sctk::reexports::client::protocol::wl_seat::WlSeat;
+    use sctk::reexports::client::{Display, GlobalManager};
+    use sctk::reexports::protocols::wlr::unstable::data_control::v1::client::zwlr_data_control_manager_v1::ZwlrDataControlManagerV1;
+    use sctk::reexports::protocols::wlr::unstable::data_control::v1::client::zwlr_data_control_source_v1::Event as SourceEvent;
+
+    let display = Display::connect_to_env().unwrap();
+    let mut queue = display.create_event_queue();
+    let attached = display.attach(queue.token());
+    let globals = GlobalManager::new(&attached);
+    queue.sync_roundtrip(&mut (), |_, _, _| {}).unwrap();
+
+    let seat = globals.instantiate_exact::<WlSeat>(1).unwrap();
+    let manager = globals.instantiate_exact::<ZwlrDataControlManagerV1>(1).unwrap();
+    let device = manager.get_data_device(&seat);
+
+    let source = manager.create_data_source();
+    source.offer("text/plain;charset=utf-8".into());
+    source.quick_assign(move |_, event, _| {
+        if let SourceEvent::Send { fd, .. } = event {
+            let _ = pipes.send(fd);
+        }
+    });
+    device.set_selection(Some(&source));
+    queue.sync_roundtrip(&mut (), |_, _, _| {}).unwrap();
+    ready.send(()).unwrap();
+
+    while queue.dispatch(&mut (), |_, _, _| {}).is_ok() {}
+}
+
+#[test]
+fn linux_context_without_focus() {
+    use copypasta::linux_clipboard::LinuxClipboardContext;
+
+    // A CLI tool never gets focus, so the Wayland backend is only picked with data control.
+    let (_lock, ..) = match connect() {
+        Some(connection) => connection,
+        None => return,
+    };
+    let owner = LinuxClipboardContext::new().unwrap();
+    let reader = LinuxClipboardContext::new().unwrap();
+    assert!(matches!(owner, LinuxClipboardContext::Wayland(_)));
+
+    owner.set_contents("from a CLI".into()).unwrap();
+    assert_eq!(reader.get_contents().unwrap(), "from a CLI");
+}
+
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
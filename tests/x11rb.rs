@@ -0,0 +1,706 @@
+#![cfg(all(
+    unix,
+    not(any(
+        target_os = "macos",
+        target_os = "android",
+        target_os = "ios",
+        target_os = "emscripten"
+    )),
+    feature = "x11"
+))]
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use copypasta::x11rb_clipboard::{Clipboard, Primary, X11RbClipboardContext};
+use copypasta::{ClipboardError, ClipboardEvent, ClipboardProvider, ContentType};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ConnectionExt as _, CreateWindowAux, EventMask, GetPropertyReply, PropMode,
+    SelectionNotifyEvent, Window, WindowClass, SELECTION_NOTIFY_EVENT,
+};
+#[cfg(feature = "image-data")]
+use x11rb::protocol::xproto::{ChangeGCAux, CreateGCAux, Rectangle};
+use x11rb::protocol::Event;
+use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{COPY_DEPTH_FROM_PARENT, CURRENT_TIME, NONE};
+
+/// All tests share the same selections, so they must not run concurrently.
+static DISPLAY_LOCK: Mutex<()> = Mutex::new(());
+
+/// Get exclusive access to the display, or `None` if there is no X server to talk to (e.g.
+/// outside of Xvfb).
+fn display() -> Option<MutexGuard<'static, ()>> {
+    std::env::var_os("DISPLAY")?;
+    Some(DISPLAY_LOCK.lock().unwrap_or_else(|err| err.into_inner()))
+}
+
+#[test]
+fn set_and_get_from_another_context() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let owner = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    let reader = X11RbClipboardContext::<Clipboard>::new().unwrap();
+
+    owner.set_contents("Hello, world!".into()).unwrap();
+    assert_eq!(reader.get_contents().unwrap(), "Hello, world!");
+
+    owner.set_contents("Goodbye".into()).unwrap();
+    assert_eq!(reader.get_contents().unwrap(), "Goodbye");
+}
+
+#[test]
+fn set_and_get_from_same_context() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let ctx = X11RbClipboardContext::<Clipboard>::new().unwrap();
+
+    ctx.set_contents("ünïcödé".into()).unwrap();
+    assert_eq!(ctx.get_contents().unwrap(), "ünïcödé");
+}
+
+#[test]
+fn lose_ownership_to_another_context() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let first = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    let second = X11RbClipboardContext::<Clipboard>::new().unwrap();
+
+    first.set_contents("first".into()).unwrap();
+    second.set_contents("second".into()).unwrap();
+    assert_eq!(first.get_contents().unwrap(), "second");
+}
+
+#[test]
+fn incr_transfer() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let owner = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    let reader = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    reader.set_timeout(Duration::from_secs(30));
+
+    // Well above the maximum request length, even with BIG-REQUESTS.
+    let payload: String = (0..50 * 1024 * 1024).map(|i| (b'a' + (i % 26) as u8) as char).collect();
+
+    owner.set_contents(payload.clone()).unwrap();
+    assert!(reader.get_contents().unwrap() == payload);
+
+    // The owner must also be able to read back its own large value.
+    assert!(owner.get_contents().unwrap() == payload);
+}
+
+#[test]
+fn no_owner() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let reader = X11RbClipboardContext::<Clipboard>::new().unwrap();
+
+    let owner = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    owner.set_contents("short-lived".into()).unwrap();
+    drop(owner);
+
+    assert!(matches!(reader.get_contents(), Err(ClipboardError::Empty)));
+    assert!(reader.get_content_types().unwrap().is_empty());
+}
+
+#[test]
+fn selections_are_independent() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let clipboard = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    let primary = X11RbClipboardContext::<Primary>::new().unwrap();
+    let primary_reader = X11RbClipboardContext::<Primary>::new().unwrap();
+
+    clipboard.set_contents("clipboard".into()).unwrap();
+    primary.set_contents("primary".into()).unwrap();
+
+    assert_eq!(primary_reader.get_contents().unwrap(), "primary");
+    assert_eq!(clipboard.get_contents().unwrap(), "clipboard");
+}
+
+#[test]
+fn content_type_normalization() {
+    type Context = X11RbClipboardContext<Clipboard>;
+
+    let normalize = |name: &str| Context::normalize_content_type(ContentType::Custom(name.into()));
+    assert_eq!(normalize("UTF8_STRING"), ContentType::Text);
+    assert_eq!(normalize("text/plain;charset=utf-8"), ContentType::Text);
+    assert_eq!(normalize("text/html"), ContentType::Html);
+    assert_eq!(normalize("image/png"), ContentType::Png);
+    assert_eq!(normalize("text/rtf"), ContentType::Rtf);
+    assert_eq!(normalize("application/pdf"), ContentType::Pdf);
+    assert_eq!(normalize("text/uri-list"), ContentType::Url);
+    assert_eq!(normalize("image/bmp"), ContentType::Custom("image/bmp".into()));
+
+    assert_eq!(Context::denormalize_content_type(ContentType::Text), "UTF8_STRING");
+    assert_eq!(Context::denormalize_content_type(ContentType::Html), "text/html");
+    assert_eq!(Context::denormalize_content_type(ContentType::Custom("foo".into())), "foo");
+}
+
+#[test]
+fn content_types() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let ctx = X11RbClipboardContext::<Clipboard>::new().unwrap();
+
+    ctx.set_contents("text".into()).unwrap();
+    let cts = ctx.get_content_types().unwrap();
+    assert!(cts.contains(&ContentType::Text));
+    assert!(!cts.contains(&ContentType::Custom("TARGETS".into())));
+}
+
+#[test]
+fn multiple_content_types() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let owner = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    let reader = X11RbClipboardContext::<Clipboard>::new().unwrap();
+
+    let custom = ContentType::Custom("application/x-copypasta-test".into());
+    let mut map = HashMap::new();
+    map.insert(ContentType::Html, b"<b>bold</b>".to_vec());
+    map.insert(ContentType::Text, b"bold".to_vec());
+    map.insert(custom.clone(), vec![0, 1, 2, 255]);
+    owner.set_content_types(map).unwrap();
+
+    let cts = reader.get_content_types().unwrap();
+    assert!(cts.contains(&ContentType::Html));
+    assert!(cts.contains(&ContentType::Text));
+    assert!(cts.contains(&custom));
+
+    assert_eq!(reader.get_content_for_type(&ContentType::Html).unwrap(), b"<b>bold</b>");
+    assert_eq!(reader.get_content_for_type(&custom).unwrap(), [0, 1, 2, 255]);
+    assert_eq!(reader.get_contents().unwrap(), "bold");
+
+    let result = reader.get_content_for_type(&ContentType::Png);
+    assert!(matches!(result, Err(ClipboardError::FormatNotAvailable)));
+}
+
+#[test]
+fn watch() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let watcher = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    let watch = watcher.watch().unwrap();
+
+    let owner = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    owner.set_contents("watched".into()).unwrap();
+    assert_eq!(watch.next_timeout(Duration::from_secs(5)), Some(ClipboardEvent::Changed));
+
+    drop(owner);
+    assert_eq!(watch.next_timeout(Duration::from_secs(5)), Some(ClipboardEvent::Cleared));
+}
+
+#[test]
+fn latin1_fallback() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let owner = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    let reader = X11RbClipboardContext::<Clipboard>::new().unwrap();
+
+    // Only offer `STRING`, like applications predating UTF-8 support.
+    let mut map = HashMap::new();
+    map.insert(ContentType::Custom("STRING".into()), b"caf\xe9".to_vec());
+    owner.set_content_types(map).unwrap();
+
+    assert_eq!(reader.get_contents().unwrap(), "café");
+}
+
+#[test]
+fn compound_text_fallback() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let owner = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    let reader = X11RbClipboardContext::<Clipboard>::new().unwrap();
+
+    let compound_text = ContentType::Custom("COMPOUND_TEXT".into());
+    let mut map = HashMap::new();
+    map.insert(compound_text.clone(), b"caf\xe9 \x1b%G\xe2\x82\xac\x1b%@!".to_vec());
+    owner.set_content_types(map).unwrap();
+    assert_eq!(reader.get_contents().unwrap(), "café €!");
+
+    // Text in Latin-2 can only be read lossily.
+    let mut map = HashMap::new();
+    map.insert(compound_text, b"\x1b-B\xb1\x1b-A\xe9".to_vec());
+    owner.set_content_types(map).unwrap();
+    assert!(matches!(reader.get_contents(), Err(ClipboardError::Backend(_))));
+    assert_eq!(reader.get_contents_lossy().unwrap(), "\u{fffd}é");
+}
+
+#[test]
+fn lossy_utf8() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let owner = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    let reader = X11RbClipboardContext::<Clipboard>::new().unwrap();
+
+    let mut map = HashMap::new();
+    map.insert(ContentType::Custom("UTF8_STRING".into()), b"ok\xff".to_vec());
+    owner.set_content_types(map).unwrap();
+
+    assert!(matches!(reader.get_contents(), Err(ClipboardError::InvalidUtf8)));
+    assert_eq!(reader.get_contents_lossy().unwrap(), "ok\u{fffd}");
+}
+
+#[test]
+fn shared_between_threads() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let owner = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    let reader = Arc::new(X11RbClipboardContext::<Clipboard>::new().unwrap());
+    reader.set_timeout(Duration::from_secs(1));
+
+    let mut map = HashMap::new();
+    map.insert(ContentType::Text, b"shared".to_vec());
+    map.insert(ContentType::Html, b"<b>shared</b>".to_vec());
+    owner.set_content_types(map).unwrap();
+
+    // Every thread must receive the events of its own conversions, without stalling until the
+    // timeout because another thread read them first.
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            let reader = reader.clone();
+            thread::spawn(move || {
+                for _ in 0..20 {
+                    assert_eq!(reader.get_contents().unwrap(), "shared");
+                    let html = reader.get_content_for_type(&ContentType::Html).unwrap();
+                    assert_eq!(html, b"<b>shared</b>");
+                    assert!(reader.get_content_types().unwrap().contains(&ContentType::Html));
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+#[test]
+fn async_get_contents() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let owner = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    let reader = X11RbClipboardContext::<Clipboard>::new().unwrap();
+
+    owner.set_contents("awaited".into()).unwrap();
+
+    // Poll without ever being woken up, no executor is needed to drive the read.
+    let mut read = copypasta::AsyncClipboardProvider::get_contents(&reader);
+    let result = loop {
+        if let Poll::Ready(result) = poll_once(&mut read) {
+            break result;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    assert_eq!(result.unwrap(), "awaited");
+}
+
+#[test]
+fn async_concurrent_reads() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let owner = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    let reader = X11RbClipboardContext::<Clipboard>::new().unwrap();
+
+    let mut map = HashMap::new();
+    map.insert(ContentType::Text, b"text".to_vec());
+    map.insert(ContentType::Html, b"<p>html</p>".to_vec());
+    owner.set_content_types(map).unwrap();
+
+    // Both reads are in flight at the same time on the same context.
+    let mut text = copypasta::AsyncClipboardProvider::get_contents(&reader);
+    let mut html =
+        copypasta::AsyncClipboardProvider::get_content_for_type(&reader, &ContentType::Html);
+    let (mut text_result, mut html_result) = (None, None);
+    while text_result.is_none() || html_result.is_none() {
+        if let Poll::Ready(result) = poll_once(&mut text) {
+            text_result = Some(result);
+        }
+        if let Poll::Ready(result) = poll_once(&mut html) {
+            html_result = Some(result);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(text_result.unwrap().unwrap(), "text");
+    assert_eq!(html_result.unwrap().unwrap(), b"<p>html</p>");
+}
+
+#[test]
+fn async_read_cancellation() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let reader = X11RbClipboardContext::<Clipboard>::new().unwrap();
+
+    // Own the clipboard without ever answering.
+    let (connection, window) = connect();
+    let clipboard = intern_atom(&connection, "CLIPBOARD");
+    connection.set_selection_owner(window, clipboard, CURRENT_TIME).unwrap().check().unwrap();
+
+    let mut read = copypasta::AsyncClipboardProvider::get_contents(&reader);
+    let requestor = loop {
+        assert!(poll_once(&mut read).is_pending());
+        if let Some(Event::SelectionRequest(ev)) = connection.poll_for_event().unwrap() {
+            break ev.requestor;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+
+    // Dropping the read gives up on the conversion, destroying the requestor window.
+    drop(read);
+    let deadline = Instant::now() + Duration::from_secs(3);
+    while connection.get_window_attributes(requestor).unwrap().reply().is_ok() {
+        assert!(Instant::now() < deadline, "requestor window still exists");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn multiple_targets() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let owner = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    owner.set_contents("multiple".into()).unwrap();
+
+    let (connection, window) = connect();
+    let atom = |name: &str| intern_atom(&connection, name);
+    let (pairs, text, png) =
+        (atom("COPYPASTA_PAIRS"), atom("COPYPASTA_TEXT"), atom("COPYPASTA_PNG"));
+
+    // Ask for text and PNG at once, only the former is available.
+    let request = [atom("UTF8_STRING"), text, atom("image/png"), png];
+    connection
+        .change_property32(PropMode::REPLACE, window, pairs, atom("ATOM_PAIR"), &request)
+        .unwrap();
+    connection
+        .convert_selection(window, atom("CLIPBOARD"), atom("MULTIPLE"), pairs, CURRENT_TIME)
+        .unwrap();
+    connection.flush().unwrap();
+
+    loop {
+        match connection.wait_for_event().unwrap() {
+            Event::SelectionNotify(ev) => {
+                assert_eq!(ev.property, pairs);
+                break;
+            },
+            _ => continue,
+        }
+    }
+
+    let converted: Vec<Atom> =
+        get_property(&connection, window, pairs).value32().unwrap().collect();
+    assert_eq!(converted, [request[0], text, request[2], NONE]);
+    assert_eq!(get_property(&connection, window, text).value, b"multiple");
+}
+
+#[test]
+fn multiple_from_destroyed_requestor() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let owner = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    owner.set_contents("survives".into()).unwrap();
+
+    // The owner can't read the pairs of a requestor which is already gone.
+    let (connection, window) = connect();
+    let atom = |name: &str| intern_atom(&connection, name);
+    let (clipboard, multiple, pairs) =
+        (atom("CLIPBOARD"), atom("MULTIPLE"), atom("COPYPASTA_PAIRS"));
+    connection.convert_selection(window, clipboard, multiple, pairs, CURRENT_TIME).unwrap();
+    connection.destroy_window(window).unwrap().check().unwrap();
+
+    let reader = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    assert_eq!(reader.get_contents().unwrap(), "survives");
+}
+
+#[cfg(feature = "image-data")]
+#[test]
+fn pixmap_image() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let reader = X11RbClipboardContext::<Clipboard>::new().unwrap();
+
+    let (ready_sender, ready) = mpsc::channel();
+    let (done, done_receiver) = mpsc::channel();
+    let owner = thread::spawn(move || serve_pixmap(ready_sender, done_receiver));
+    ready.recv().unwrap();
+
+    let image = reader.get_image().unwrap();
+    done.send(()).unwrap();
+    owner.join().unwrap();
+
+    assert_eq!((image.width, image.height), (2, 1));
+    assert_eq!(image.rgba, [255, 255, 255, 255, 0, 0, 0, 255]);
+}
+
+#[test]
+fn notify_with_current_time() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let reader = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    reader.set_timeout(Duration::from_secs(10));
+
+    let (connection, window) = connect();
+    let (clipboard, utf8_string) =
+        (intern_atom(&connection, "CLIPBOARD"), intern_atom(&connection, "UTF8_STRING"));
+    connection.set_selection_owner(window, clipboard, CURRENT_TIME).unwrap().check().unwrap();
+
+    // Answer with `CurrentTime`, rather than the time of the request.
+    let owner = thread::spawn(move || loop {
+        let ev = match connection.wait_for_event().unwrap() {
+            Event::SelectionRequest(ev) => ev,
+            _ => continue,
+        };
+        connection
+            .change_property8(PropMode::REPLACE, ev.requestor, ev.property, utf8_string, b"now")
+            .unwrap();
+
+        let notify = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: CURRENT_TIME,
+            requestor: ev.requestor,
+            selection: ev.selection,
+            target: ev.target,
+            property: ev.property,
+        };
+        connection.send_event(false, ev.requestor, EventMask::NO_EVENT, notify).unwrap();
+        connection.flush().unwrap();
+        return;
+    });
+
+    let start = Instant::now();
+    assert_eq!(reader.get_contents().unwrap(), "now");
+    assert!(start.elapsed() < Duration::from_secs(10));
+    owner.join().unwrap();
+}
+
+#[test]
+fn persist_without_manager() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let (connection, _) = connect();
+    let manager = intern_atom(&connection, "CLIPBOARD_MANAGER");
+    if connection.get_selection_owner(manager).unwrap().reply().unwrap().owner != NONE {
+        return;
+    }
+
+    // There is nothing to save until something was copied.
+    let ctx = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    ctx.persist().unwrap();
+
+    ctx.set_contents("unsaved".into()).unwrap();
+    assert!(matches!(ctx.persist(), Err(ClipboardError::Unsupported)));
+
+    // Clipboard managers only save the clipboard.
+    let primary = X11RbClipboardContext::<Primary>::new().unwrap();
+    primary.set_contents("primary".into()).unwrap();
+    assert!(matches!(primary.persist(), Err(ClipboardError::Unsupported)));
+}
+
+#[test]
+fn persist_to_manager() {
+    let _display = match display() {
+        Some(display) => display,
+        None => return,
+    };
+    let (ready_sender, ready) = mpsc::channel();
+    let manager = thread::spawn(move || run_manager(ready_sender));
+    ready.recv().unwrap();
+
+    let owner = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    owner.set_contents("persisted".into()).unwrap();
+    drop(owner);
+
+    // The manager took over the clipboard before the owner went away.
+    let saved = manager.join().unwrap();
+    let reader = X11RbClipboardContext::<Clipboard>::new().unwrap();
+    assert_eq!(reader.get_contents().unwrap(), "persisted");
+    drop(saved);
+}
+
+/// Act as a clipboard manager until asked to save the clipboard, returning the context which now
+/// owns the saved contents.
+fn run_manager(ready: mpsc::Sender<()>) -> X11RbClipboardContext<Clipboard> {
+    let (connection, window) = connect();
+    let manager = intern_atom(&connection, "CLIPBOARD_MANAGER");
+    connection.set_selection_owner(window, manager, CURRENT_TIME).unwrap().check().unwrap();
+    ready.send(()).unwrap();
+
+    loop {
+        let ev = match connection.wait_for_event().unwrap() {
+            Event::SelectionRequest(ev) => ev,
+            _ => continue,
+        };
+        assert_eq!(ev.target, intern_atom(&connection, "SAVE_TARGETS"));
+
+        let saved = X11RbClipboardContext::<Clipboard>::new().unwrap();
+        let text = saved.get_contents().unwrap();
+        saved.set_contents(text).unwrap();
+
+        let notify = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: ev.time,
+            requestor: ev.requestor,
+            selection: ev.selection,
+            target: ev.target,
+            property: ev.property,
+        };
+        connection.send_event(false, ev.requestor, EventMask::NO_EVENT, notify).unwrap();
+        connection.flush().unwrap();
+
+        // Closing the connection gives up `CLIPBOARD_MANAGER`, so dropping the returned context
+        // doesn't try to persist to ourselves.
+        return saved;
+    }
+}
+
+/// Own the clipboard with a pixmap of a white and a black pixel, like clients predating image
+/// file formats, until `done` receives a message.
+#[cfg(feature = "image-data")]
+fn serve_pixmap(ready: mpsc::Sender<()>, done: mpsc::Receiver<()>) {
+    let (connection, window) = connect();
+    let screen = &connection.setup().roots[0];
+    let (targets, clipboard) =
+        (intern_atom(&connection, "TARGETS"), intern_atom(&connection, "CLIPBOARD"));
+
+    let pixmap = connection.generate_id().unwrap();
+    connection.create_pixmap(screen.root_depth, pixmap, window, 2, 1).unwrap();
+    let gc = connection.generate_id().unwrap();
+    connection.create_gc(gc, pixmap, &CreateGCAux::new()).unwrap();
+    for (x, pixel) in [(0, screen.white_pixel), (1, screen.black_pixel)] {
+        connection.change_gc(gc, &ChangeGCAux::new().foreground(pixel)).unwrap();
+        let rectangle = Rectangle { x, y: 0, width: 1, height: 1 };
+        connection.poly_fill_rectangle(pixmap, gc, &[rectangle]).unwrap();
+    }
+
+    connection.set_selection_owner(window, clipboard, CURRENT_TIME).unwrap().check().unwrap();
+    ready.send(()).unwrap();
+
+    let mut served = false;
+    while !served {
+        let ev = match connection.wait_for_event().unwrap() {
+            Event::SelectionRequest(ev) => ev,
+            _ => continue,
+        };
+
+        let (type_, value): (Atom, u32) = if ev.target == targets {
+            (AtomEnum::ATOM.into(), intern_atom(&connection, "PIXMAP"))
+        } else {
+            served = true;
+            (AtomEnum::PIXMAP.into(), pixmap)
+        };
+        connection
+            .change_property32(PropMode::REPLACE, ev.requestor, ev.property, type_, &[value])
+            .unwrap();
+
+        let notify = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: ev.time,
+            requestor: ev.requestor,
+            selection: ev.selection,
+            target: ev.target,
+            property: ev.property,
+        };
+        connection.send_event(false, ev.requestor, EventMask::NO_EVENT, notify).unwrap();
+        connection.flush().unwrap();
+    }
+
+    // The pixmap is freed along with the connection, so it must outlive the read.
+    done.recv().unwrap();
+}
+
+/// Open a connection to the X server, with a window to use as requestor.
+fn connect() -> (RustConnection, Window) {
+    let (connection, screen_num) = RustConnection::connect(None).unwrap();
+    let root = connection.setup().roots[screen_num].root;
+    let window = connection.generate_id().unwrap();
+    connection
+        .create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &CreateWindowAux::new(),
+        )
+        .unwrap()
+        .check()
+        .unwrap();
+    (connection, window)
+}
+
+fn intern_atom(connection: &RustConnection, name: &str) -> Atom {
+    connection.intern_atom(false, name.as_bytes()).unwrap().reply().unwrap().atom
+}
+
+fn get_property(connection: &RustConnection, window: Window, property: Atom) -> GetPropertyReply {
+    let reply = connection.get_property(false, window, property, AtomEnum::ANY, 0, u32::MAX);
+    reply.unwrap().reply().unwrap()
+}
+
+struct NoopWaker;
+
+impl Wake for NoopWaker {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// Poll `future` once, without ever being woken up.
+fn poll_once<F: Future + Unpin>(future: &mut F) -> Poll<F::Output> {
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut cx = Context::from_waker(&waker);
+    Pin::new(future).poll(&mut cx)
+}